@@ -1,16 +1,21 @@
 #![deny(clippy::all)]
 #![deny(clippy::pedantic)]
 
-use std::{sync::Arc, time::Duration};
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
 
+use anyhow::Context;
 use axum::{
     extract::{Path, Query, State},
     http::{Method, StatusCode},
     routing::{delete, get, post, put},
     Json, Router,
 };
-use payloads::{NewTodoRequest, TodoCompletedRequest};
-use todos::filter_todos;
+use chrono::Utc;
+use payloads::{
+    BatchOperation, BatchOperationResult, BatchRequest, BatchResponse, MoveTodoRequest,
+    NewTodoRequest, RemoveTodosRequest, TagRequest, TodoCompletedRequest,
+};
+use todos::{collect_tags, filter_todos, is_blank_tag, sort_todos, tag_exists, Tag};
 use tokio::{net::TcpListener, sync::Mutex};
 use tower::ServiceBuilder;
 use tower_http::{
@@ -21,23 +26,43 @@ use uuid::Uuid;
 
 use crate::errors::Result;
 use crate::payloads::TodosFilterRequest;
-use crate::todos::Todo;
+use crate::todos::{Todo, TrashedTodo};
 
 mod errors;
 mod payloads;
+mod tasks;
 mod todos;
 
+/// Number of days a todo stays in the trash before being purged, overridable with `TODOS_TRASH_RETENTION_DAYS`
+static DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
+
+// When several are needed, `todos` must always be locked before `trash` and `tags`
 #[derive(Clone, Default)]
 struct AppState {
     todos: Arc<Mutex<Vec<Todo>>>,
+    trash: Arc<Mutex<Vec<TrashedTodo>>>,
+    /// The tags created on their own, listed even when no todo has them
+    tags: Arc<Mutex<BTreeSet<String>>>,
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
 
     let state = AppState::default();
 
+    // Background tasks
+    let trash_retention_days = match std::env::var("TODOS_TRASH_RETENTION_DAYS") {
+        Ok(days) => days
+            .parse::<u32>()
+            .with_context(|| format!("invalid TODOS_TRASH_RETENTION_DAYS {days}"))?,
+        Err(_) => DEFAULT_TRASH_RETENTION_DAYS,
+    };
+    tasks::spawn_trash_purge(
+        state.clone(),
+        chrono::Duration::days(i64::from(trash_retention_days)),
+    );
+
     // Middlewares
     let cors = CorsLayer::new()
         .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
@@ -49,10 +74,21 @@ async fn main() {
     let exposed_router = Router::new().route("/", get(root));
 
     let protected_router = Router::new()
-        .route("/todos", get(todos))
+        .route("/todos", get(todos).delete(remove_todos))
         .route("/todos/new", post(create_todo))
+        .route("/todos/batch", post(batch_todos))
+        .route("/todos/trash", get(trashed_todos).delete(empty_trash))
         .route("/todos/:id/set-completion", put(set_todo_completion))
+        .route("/todos/:id/move", post(move_todo))
         .route("/todos/:id/remove", delete(remove_todo))
+        .route("/todos/:id/restore", post(restore_todo))
+        .route("/todos/:id/purge", delete(purge_todo))
+        .route("/todos/:id/tags", post(add_todo_tag))
+        .route("/todos/:id/tags/:tag", delete(remove_todo_tag))
+        .route("/tags", get(all_tags))
+        .route("/tags/new", post(create_tag))
+        .route("/tags/:tag/rename", put(rename_tag))
+        .route("/tags/:tag/remove", delete(remove_tag))
         .with_state(state)
         .layer(ServiceBuilder::new().layer(cors).layer(timeout));
 
@@ -61,6 +97,8 @@ async fn main() {
     // Starting
     let listener = TcpListener::bind("0.0.0.0:8000").await.unwrap();
     axum::serve(listener, app).await.unwrap();
+
+    Ok(())
 }
 
 async fn root() -> &'static str {
@@ -72,23 +110,36 @@ async fn todos(
     todos_filters: Option<Query<TodosFilterRequest>>,
 ) -> Result<Json<Vec<Todo>>> {
     let todos = state.todos.lock().await;
+    let todos_filters = todos_filters.unwrap_or_default();
+
+    let mut todos = filter_todos(&todos, todos_filters.filter);
+    if let Some(priority) = todos_filters.priority {
+        todos.retain(|todo| todo.priority == Some(priority));
+    }
+    if let Some(tag) = &todos_filters.tag {
+        todos.retain(|todo| todo.tags.contains(tag));
+    }
+    if let Some(sort) = todos_filters.sort {
+        sort_todos(&mut todos, sort);
+    }
 
-    Ok(Json(filter_todos(
-        &todos,
-        todos_filters.unwrap_or_default().filter,
-    )))
+    Ok(Json(todos))
 }
 
 async fn create_todo(
     State(state): State<AppState>,
     Json(new_todo): Json<NewTodoRequest>,
 ) -> StatusCode {
+    if new_todo.tags.iter().any(|tag| is_blank_tag(tag)) {
+        return StatusCode::UNPROCESSABLE_ENTITY;
+    }
+
     let mut todos = state.todos.lock().await;
-    todos.push(Todo {
-        id: Uuid::new_v4(),
-        content: new_todo.content,
-        completed: false,
-    });
+    todos.push(Todo::new(
+        new_todo.content,
+        new_todo.priority,
+        new_todo.tags,
+    ));
     StatusCode::NO_CONTENT
 }
 
@@ -107,13 +158,274 @@ async fn set_todo_completion(
     StatusCode::NO_CONTENT
 }
 
+// The order of the todos in the `Vec` is the order chosen by the user
+async fn move_todo(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Json(todo_move): Json<MoveTodoRequest>,
+) -> StatusCode {
+    let mut todos = state.todos.lock().await;
+    let Some(index) = todos.iter().position(|todo| todo.id == todo_id) else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    let todo = todos.remove(index);
+    let index_of = |id| todos.iter().position(|todo| todo.id == id);
+    let target = match (todo_move.after, todo_move.before) {
+        (None, None) => None,
+        (Some(after), None) => index_of(after).map(|index| index + 1),
+        (None, Some(before)) => index_of(before),
+        (Some(after), Some(before)) => index_of(after)
+            .map(|index| index + 1)
+            .filter(|index| index_of(before).is_some_and(|before| *index <= before)),
+    };
+    let Some(target) = target else {
+        todos.insert(index, todo);
+        return StatusCode::UNPROCESSABLE_ENTITY;
+    };
+    todos.insert(target, todo);
+
+    StatusCode::NO_CONTENT
+}
+
 async fn remove_todo(Path(todo_id): Path<Uuid>, State(state): State<AppState>) -> StatusCode {
     let mut todos = state.todos.lock().await;
-    let original_len = todos.len();
-    todos.retain(|todo| todo.id != todo_id);
-    if original_len == todos.len() {
+    let Some(index) = todos.iter().position(|todo| todo.id == todo_id) else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    let todo = todos.remove(index);
+    state.trash.lock().await.push(TrashedTodo {
+        todo,
+        deleted_at: Utc::now(),
+    });
+
+    StatusCode::NO_CONTENT
+}
+
+// The filter is mandatory here, a bare `DELETE /todos` must not wipe everything
+async fn remove_todos(
+    State(state): State<AppState>,
+    Query(removal): Query<RemoveTodosRequest>,
+) -> StatusCode {
+    let mut todos = state.todos.lock().await;
+    let mut trash = state.trash.lock().await;
+    let deleted_at = Utc::now();
+    let (removed_todos, kept_todos) = todos
+        .drain(..)
+        .partition(|todo| removal.filter.matches(todo));
+    *todos = kept_todos;
+    trash.extend(
+        removed_todos
+            .into_iter()
+            .map(|todo| TrashedTodo { todo, deleted_at }),
+    );
+
+    StatusCode::NO_CONTENT
+}
+
+async fn trashed_todos(State(state): State<AppState>) -> Json<Vec<TrashedTodo>> {
+    let trash = state.trash.lock().await;
+
+    Json(trash.clone())
+}
+
+async fn restore_todo(Path(todo_id): Path<Uuid>, State(state): State<AppState>) -> StatusCode {
+    let mut todos = state.todos.lock().await;
+    let mut trash = state.trash.lock().await;
+    let Some(index) = trash
+        .iter()
+        .position(|trashed_todo| trashed_todo.todo.id == todo_id)
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    todos.push(trash.remove(index).todo);
+
+    StatusCode::NO_CONTENT
+}
+
+async fn purge_todo(Path(todo_id): Path<Uuid>, State(state): State<AppState>) -> StatusCode {
+    let mut trash = state.trash.lock().await;
+    let original_len = trash.len();
+    trash.retain(|trashed_todo| trashed_todo.todo.id != todo_id);
+    if original_len == trash.len() {
         return StatusCode::NOT_FOUND;
     }
 
     StatusCode::NO_CONTENT
 }
+
+async fn empty_trash(State(state): State<AppState>) -> StatusCode {
+    state.trash.lock().await.clear();
+
+    StatusCode::NO_CONTENT
+}
+
+async fn add_todo_tag(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Json(tag): Json<TagRequest>,
+) -> StatusCode {
+    if is_blank_tag(&tag.name) {
+        return StatusCode::UNPROCESSABLE_ENTITY;
+    }
+
+    let mut todos = state.todos.lock().await;
+    let Some(todo) = todos.iter_mut().find(|todo| todo.id == todo_id) else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    todo.add_tag(tag.name);
+
+    StatusCode::NO_CONTENT
+}
+
+async fn remove_todo_tag(
+    Path((todo_id, tag)): Path<(Uuid, String)>,
+    State(state): State<AppState>,
+) -> StatusCode {
+    let mut todos = state.todos.lock().await;
+    let Some(todo) = todos.iter_mut().find(|todo| todo.id == todo_id) else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    if !todo.remove_tag(&tag) {
+        return StatusCode::NOT_FOUND;
+    }
+
+    StatusCode::NO_CONTENT
+}
+
+async fn all_tags(State(state): State<AppState>) -> Json<Vec<Tag>> {
+    let todos = state.todos.lock().await;
+    let tags = state.tags.lock().await;
+
+    Json(collect_tags(&todos, &tags))
+}
+
+async fn create_tag(State(state): State<AppState>, Json(tag): Json<TagRequest>) -> StatusCode {
+    if is_blank_tag(&tag.name) {
+        return StatusCode::UNPROCESSABLE_ENTITY;
+    }
+
+    let todos = state.todos.lock().await;
+    let mut tags = state.tags.lock().await;
+    if tag_exists(&todos, &tags, &tag.name) {
+        return StatusCode::CONFLICT;
+    }
+    tags.insert(tag.name);
+
+    StatusCode::NO_CONTENT
+}
+
+async fn rename_tag(
+    Path(tag): Path<String>,
+    State(state): State<AppState>,
+    Json(new_tag): Json<TagRequest>,
+) -> StatusCode {
+    if is_blank_tag(&new_tag.name) {
+        return StatusCode::UNPROCESSABLE_ENTITY;
+    }
+
+    let mut todos = state.todos.lock().await;
+    let mut tags = state.tags.lock().await;
+    if new_tag.name != tag && tag_exists(&todos, &tags, &new_tag.name) {
+        return StatusCode::CONFLICT;
+    }
+    let mut found = tags.remove(&tag);
+    if found {
+        tags.insert(new_tag.name.clone());
+    }
+    for todo in todos.iter_mut() {
+        if todo.remove_tag(&tag) {
+            todo.add_tag(new_tag.name.clone());
+            found = true;
+        }
+    }
+    if !found {
+        return StatusCode::NOT_FOUND;
+    }
+
+    StatusCode::NO_CONTENT
+}
+
+async fn remove_tag(Path(tag): Path<String>, State(state): State<AppState>) -> StatusCode {
+    let mut todos = state.todos.lock().await;
+    let mut found = state.tags.lock().await.remove(&tag);
+    for todo in todos.iter_mut() {
+        found |= todo.remove_tag(&tag);
+    }
+    if !found {
+        return StatusCode::NOT_FOUND;
+    }
+
+    StatusCode::NO_CONTENT
+}
+
+// The operations are applied on a copy of the todos while holding the lock,
+// the copy replaces the todos only if all the operations succeeded
+async fn batch_todos(
+    State(state): State<AppState>,
+    Json(batch): Json<BatchRequest>,
+) -> Result<Json<BatchResponse>, (StatusCode, String)> {
+    let mut todos = state.todos.lock().await;
+    let mut trash = state.trash.lock().await;
+    let mut new_todos = todos.clone();
+    let mut new_trashed_todos = Vec::new();
+    let mut results = Vec::with_capacity(batch.operations.len());
+
+    for (index, operation) in batch.operations.into_iter().enumerate() {
+        let result =
+            match operation {
+                BatchOperation::Create(new_todo) => {
+                    if new_todo.tags.iter().any(|tag| is_blank_tag(tag)) {
+                        return Err((
+                            StatusCode::UNPROCESSABLE_ENTITY,
+                            format!("operation {index} failed: a tag name can't be blank"),
+                        ));
+                    }
+                    let todo = Todo::new(new_todo.content, new_todo.priority, new_todo.tags);
+                    let id = todo.id;
+                    new_todos.push(todo);
+                    Some(BatchOperationResult::Created { id })
+                }
+                BatchOperation::Update {
+                    id,
+                    content,
+                    completed,
+                } => new_todos.iter_mut().find(|todo| todo.id == id).map(|todo| {
+                    if let Some(content) = content {
+                        todo.content = content;
+                    }
+                    if let Some(completed) = completed {
+                        todo.completed = completed;
+                    }
+                    BatchOperationResult::Updated { id }
+                }),
+                BatchOperation::Delete { id } => new_todos
+                    .iter()
+                    .position(|todo| todo.id == id)
+                    .map(|index| {
+                        new_trashed_todos.push(TrashedTodo {
+                            todo: new_todos.remove(index),
+                            deleted_at: Utc::now(),
+                        });
+                        BatchOperationResult::Deleted { id }
+                    }),
+            };
+
+        let Some(result) = result else {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("operation {index} failed: not found"),
+            ));
+        };
+        results.push(result);
+    }
+
+    *todos = new_todos;
+    trash.extend(new_trashed_todos);
+
+    Ok(Json(BatchResponse { results }))
+}
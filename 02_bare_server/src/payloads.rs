@@ -1,26 +1,79 @@
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use crate::todos::TodosFilter;
+use crate::todos::{Priority, TodosFilter, TodosSort};
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TodosFilterRequest {
+    #[serde(default)]
     pub filter: TodosFilter,
+    pub priority: Option<Priority>,
+    pub tag: Option<String>,
+    pub sort: Option<TodosSort>,
 }
 
-impl Default for TodosFilterRequest {
-    fn default() -> Self {
-        Self {
-            filter: TodosFilter::All,
-        }
-    }
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoveTodosRequest {
+    pub filter: TodosFilter,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct NewTodoRequest {
     pub content: String,
+    pub priority: Option<Priority>,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TodoCompletedRequest {
     pub completed: bool,
 }
+
+/// Places the todo right after `after` and/or right before `before`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MoveTodoRequest {
+    pub after: Option<Uuid>,
+    pub before: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchRequest {
+    pub operations: Vec<BatchOperation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op")]
+pub enum BatchOperation {
+    #[serde(rename = "create")]
+    Create(NewTodoRequest),
+    #[serde(rename = "update")]
+    Update {
+        id: Uuid,
+        content: Option<String>,
+        completed: Option<bool>,
+    },
+    #[serde(rename = "delete")]
+    Delete { id: Uuid },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op")]
+pub enum BatchOperationResult {
+    #[serde(rename = "create")]
+    Created { id: Uuid },
+    #[serde(rename = "update")]
+    Updated { id: Uuid },
+    #[serde(rename = "delete")]
+    Deleted { id: Uuid },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchResponse {
+    pub results: Vec<BatchOperationResult>,
+}
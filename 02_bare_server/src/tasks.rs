@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use tokio::{task::JoinHandle, time::interval};
+use tracing::debug;
+
+use crate::AppState;
+
+static TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Periodically and permanently deletes the todos that stayed in the trash longer than `retention`
+pub fn spawn_trash_purge(state: AppState, retention: chrono::Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = interval(TRASH_PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            // A retention reaching before the earliest representable date keeps the whole trash
+            let Some(deleted_before) = Utc::now().checked_sub_signed(retention) else {
+                continue;
+            };
+            let mut trash = state.trash.lock().await;
+            let original_len = trash.len();
+            trash.retain(|trashed_todo| trashed_todo.deleted_at >= deleted_before);
+            debug!(
+                "purged {} todo(s) from the trash",
+                original_len - trash.len()
+            );
+        }
+    })
+}
@@ -1,9 +1,16 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet},
+};
+
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[allow(clippy::module_name_repetitions)]
 pub enum TodosFilter {
+    #[default]
     #[serde(rename = "all")]
     All,
     #[serde(rename = "completed")]
@@ -12,11 +19,119 @@ pub enum TodosFilter {
     NotCompleted,
 }
 
+impl TodosFilter {
+    pub fn matches(self, todo: &Todo) -> bool {
+        match self {
+            TodosFilter::All => true,
+            TodosFilter::Completed => todo.completed,
+            TodosFilter::NotCompleted => !todo.completed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(clippy::module_name_repetitions)]
+pub enum TodosSort {
+    /// Most urgent first, todos without priority last
+    #[serde(rename = "priority")]
+    Priority,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Priority {
+    #[serde(rename = "low")]
+    Low,
+    #[serde(rename = "normal")]
+    Normal,
+    #[serde(rename = "high")]
+    High,
+    #[serde(rename = "urgent")]
+    Urgent,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Todo {
     pub id: Uuid,
     pub content: String,
     pub completed: bool,
+    pub priority: Option<Priority>,
+    pub tags: Vec<String>,
+}
+
+impl Todo {
+    pub fn new(content: String, priority: Option<Priority>, mut tags: Vec<String>) -> Self {
+        tags.sort();
+        tags.dedup();
+        Self {
+            id: Uuid::new_v4(),
+            content,
+            completed: false,
+            priority,
+            tags,
+        }
+    }
+
+    /// Returns `false` if the todo already had the tag
+    pub fn add_tag(&mut self, tag: String) -> bool {
+        match self.tags.binary_search(&tag) {
+            Ok(_) => false,
+            Err(index) => {
+                self.tags.insert(index, tag);
+                true
+            }
+        }
+    }
+
+    /// Returns `false` if the todo didn't have the tag
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        let original_len = self.tags.len();
+        self.tags.retain(|todo_tag| todo_tag != tag);
+        original_len != self.tags.len()
+    }
+}
+
+/// A todo that has been moved to the trash, it can be restored until it gets purged
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrashedTodo {
+    #[serde(flatten)]
+    pub todo: Todo,
+    pub deleted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tag {
+    pub name: String,
+    pub todos_count: usize,
+}
+
+/// Lists the tags created on their own along with the tags used by the todos, sorted by name
+pub fn collect_tags(todos: &[Todo], created_tags: &BTreeSet<String>) -> Vec<Tag> {
+    let mut tags: BTreeMap<&str, usize> =
+        created_tags.iter().map(|tag| (tag.as_str(), 0)).collect();
+    for todo in todos {
+        for tag in &todo.tags {
+            *tags.entry(tag).or_default() += 1;
+        }
+    }
+
+    tags.into_iter()
+        .map(|(name, todos_count)| Tag {
+            name: name.to_string(),
+            todos_count,
+        })
+        .collect()
+}
+
+/// Whether a todo or the created tags already have the tag
+pub fn tag_exists(todos: &[Todo], created_tags: &BTreeSet<String>, tag: &str) -> bool {
+    created_tags.contains(tag)
+        || todos
+            .iter()
+            .any(|todo| todo.tags.iter().any(|todo_tag| todo_tag == tag))
+}
+
+pub fn is_blank_tag(tag: &str) -> bool {
+    tag.trim().is_empty()
 }
 
 #[allow(clippy::module_name_repetitions)]
@@ -43,3 +158,10 @@ pub fn filter_todos(todos: &[Todo], filter: TodosFilter) -> Vec<Todo> {
         }
     }
 }
+
+pub fn sort_todos(todos: &mut [Todo], sort: TodosSort) {
+    match sort {
+        // `None` is lower than any `Some`, so reversing the order puts the todos without priority last
+        TodosSort::Priority => todos.sort_by_key(|todo| Reverse(todo.priority)),
+    }
+}
@@ -0,0 +1,286 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use axum::extract::multipart::Field;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{prelude::FromRow, Sqlite, SqliteConnection, Transaction};
+use tokio::{
+    fs::{self, File},
+    io::AsyncWriteExt,
+    sync::Mutex,
+};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::{
+    errors::{Error, Result},
+    todos,
+};
+
+/// Overridable with the `attachments.dir` setting
+pub static DEFAULT_ATTACHMENTS_DIR: &str = "attachments";
+
+/// In bytes, overridable with the `attachments.max_size` setting
+pub static DEFAULT_ATTACHMENT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+/// How long uploading or downloading an attachment can take, overridable with the
+/// `attachments.timeout_secs` setting
+pub static DEFAULT_ATTACHMENT_TIMEOUT_SECS: u64 = 300;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct Attachment {
+    pub id: Uuid,
+    pub todo_id: Uuid,
+    pub filename: String,
+    pub content_type: String,
+    pub size: i64,
+    /// Identical contents are stored once
+    pub sha256: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An uploaded content, kept in a temporary file until its attachment is created
+pub struct StagedContent {
+    path: PathBuf,
+    sha256: String,
+    size: i64,
+}
+
+/// The contents are stored in `dir`, each file being named after the SHA-256 digest of its content
+#[derive(Debug, Clone)]
+pub struct AttachmentStorage {
+    pub dir: PathBuf,
+    pub max_size: u64,
+    /// Storing a content and removing an unreferenced one are serialized, otherwise a content
+    /// could be removed right after a new attachment referring to it has been committed
+    files: Arc<Mutex<()>>,
+}
+
+impl AttachmentStorage {
+    pub fn new(dir: PathBuf, max_size: u64) -> Self {
+        Self {
+            dir,
+            max_size,
+            files: Arc::default(),
+        }
+    }
+
+    pub fn content_path(&self, sha256: &str) -> PathBuf {
+        self.dir.join(sha256)
+    }
+
+    /// Streams the field to a temporary file, hashing it and enforcing the maximum size on the way
+    pub async fn stage(&self, field: &mut Field<'_>) -> Result<StagedContent> {
+        let path = self.dir.join(format!("{}.tmp", Uuid::new_v4()));
+        match self.write(field, &path).await {
+            Ok((sha256, size)) => Ok(StagedContent { path, sha256, size }),
+            Err(err) => {
+                fs::remove_file(&path).await.ok();
+                Err(err)
+            }
+        }
+    }
+
+    async fn write(&self, field: &mut Field<'_>, path: &Path) -> Result<(String, i64)> {
+        let mut file = File::create(path).await?;
+        let mut hasher = Sha256::new();
+        let mut size = 0;
+        while let Some(chunk) = field
+            .chunk()
+            .await
+            .map_err(|err| Error::Invalid(err.body_text()))?
+        {
+            size += chunk.len() as u64;
+            if size > self.max_size {
+                return Err(Error::TooLarge(format!(
+                    "an attachment can't exceed {} bytes",
+                    self.max_size
+                )));
+            }
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+
+        Ok((format!("{:x}", hasher.finalize()), i64::try_from(size)?))
+    }
+
+    /// Moves the staged content to its place, unless the same content is already stored, then
+    /// commits the attachment referring to it. Both happen under the lock, so that the content can
+    /// neither be downloaded before being stored nor be removed as unreferenced meanwhile.
+    /// The transaction is rolled back when the content can't be stored.
+    pub async fn store(&self, staged: &StagedContent, tx: Transaction<'_, Sqlite>) -> Result<()> {
+        let _files = self.files.lock().await;
+        let path = self.content_path(&staged.sha256);
+        let moved = if fs::try_exists(&path).await? {
+            fs::remove_file(&staged.path).await?;
+            false
+        } else {
+            fs::rename(&staged.path, &path).await?;
+            true
+        };
+        if let Err(err) = tx.commit().await {
+            // Nothing else refers to the content, the others storing it are waiting for the lock
+            if moved {
+                fs::remove_file(&path).await.ok();
+            }
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+
+    pub async fn discard(&self, staged: StagedContent) {
+        fs::remove_file(staged.path).await.ok();
+    }
+
+    /// Removes the stored contents that no attachment refers to anymore
+    pub async fn remove_unreferenced(
+        &self,
+        conn: &mut SqliteConnection,
+        sha256s: &[String],
+    ) -> Result<()> {
+        let _files = self.files.lock().await;
+        for sha256 in sha256s {
+            let referenced: bool =
+                sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM attachments WHERE sha256 = ?)")
+                    .bind(sha256)
+                    .fetch_one(&mut *conn)
+                    .await?;
+            if referenced {
+                continue;
+            }
+
+            debug!("removing the unreferenced content {sha256}");
+            match fs::remove_file(self.content_path(sha256)).await {
+                Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub async fn select_attachments(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+) -> Result<Vec<Attachment>> {
+    if !todos::is_live(&mut *conn, todo_id).await? {
+        return Err(Error::NotFound);
+    }
+
+    let attachments = sqlx::query_as("SELECT id, todo_id, filename, content_type, size, sha256, created_at FROM attachments WHERE todo_id = ? ORDER BY julianday(created_at)")
+        .bind(todo_id)
+        .fetch_all(conn)
+        .await?;
+
+    Ok(attachments)
+}
+
+pub async fn select_attachment(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+    attachment_id: &Uuid,
+) -> Result<Attachment> {
+    if !todos::is_live(&mut *conn, todo_id).await? {
+        return Err(Error::NotFound);
+    }
+
+    let attachment = sqlx::query_as("SELECT id, todo_id, filename, content_type, size, sha256, created_at FROM attachments WHERE id = ? AND todo_id = ?")
+        .bind(attachment_id)
+        .bind(todo_id)
+        .fetch_optional(conn)
+        .await?;
+
+    attachment.ok_or(Error::NotFound)
+}
+
+/// Records the attachment, to commit with `AttachmentStorage::store` once its content is stored
+pub async fn create_attachment(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+    staged: &StagedContent,
+    filename: &str,
+    content_type: &str,
+) -> Result<Uuid> {
+    if !todos::is_live(&mut *conn, todo_id).await? {
+        return Err(Error::NotFound);
+    }
+
+    let attachment_id = Uuid::new_v4();
+    sqlx::query("INSERT INTO attachments (id, todo_id, filename, content_type, size, sha256, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)")
+        .bind(attachment_id)
+        .bind(todo_id)
+        .bind(filename)
+        .bind(content_type)
+        .bind(staged.size)
+        .bind(&staged.sha256)
+        .bind(Utc::now())
+        .execute(conn)
+        .await?;
+
+    Ok(attachment_id)
+}
+
+/// Returns the digest of the removed content, which may not be referenced anymore
+pub async fn remove_attachment(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+    attachment_id: &Uuid,
+) -> Result<String> {
+    if !todos::is_live(&mut *conn, todo_id).await? {
+        return Err(Error::NotFound);
+    }
+
+    let sha256 =
+        sqlx::query_scalar("DELETE FROM attachments WHERE id = ? AND todo_id = ? RETURNING sha256")
+            .bind(attachment_id)
+            .bind(todo_id)
+            .fetch_optional(conn)
+            .await?;
+
+    sha256.ok_or(Error::NotFound)
+}
+
+/// Returns the digests of the contents attached to the todo, purging it removes its attachments
+pub async fn select_todo_sha256s(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+) -> Result<Vec<String>> {
+    let sha256s = sqlx::query_scalar("SELECT DISTINCT sha256 FROM attachments WHERE todo_id = ?")
+        .bind(todo_id)
+        .fetch_all(conn)
+        .await?;
+
+    Ok(sha256s)
+}
+
+/// Returns the digests of the contents attached to todos in the trash, purging these todos
+/// removes their attachments, and possibly the last references to the contents
+pub async fn select_trashed_sha256s(conn: &mut SqliteConnection) -> Result<Vec<String>> {
+    let sha256s = sqlx::query_scalar("SELECT DISTINCT attachments.sha256 FROM attachments JOIN todos ON todos.id = attachments.todo_id WHERE todos.deleted_at IS NOT NULL")
+        .fetch_all(conn)
+        .await?;
+
+    Ok(sha256s)
+}
+
+/// Only keeps the printable ASCII characters of the filename, quotes and backslashes excluded
+pub fn content_disposition(filename: &str) -> String {
+    let filename = filename
+        .chars()
+        .map(|char| match char {
+            '"' | '\\' => '_',
+            char if char.is_ascii_graphic() || char == ' ' => char,
+            _ => '_',
+        })
+        .collect::<String>();
+
+    format!("attachment; filename=\"{filename}\"")
+}
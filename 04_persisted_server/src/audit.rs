@@ -0,0 +1,208 @@
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{request::Parts, StatusCode},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, types::Json, QueryBuilder, Sqlite, SqliteConnection};
+use tower_http::request_id::RequestId;
+use uuid::Uuid;
+
+use crate::{
+    errors::Result,
+    middlewares::CurrentUser,
+    todos::{self, TodoRecord},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+pub enum AuditAction {
+    #[serde(rename = "create")]
+    #[sqlx(rename = "create")]
+    Create,
+    #[serde(rename = "update")]
+    #[sqlx(rename = "update")]
+    Update,
+    #[serde(rename = "remove")]
+    #[sqlx(rename = "remove")]
+    Remove,
+    #[serde(rename = "restore")]
+    #[sqlx(rename = "restore")]
+    Restore,
+    #[serde(rename = "purge")]
+    #[sqlx(rename = "purge")]
+    Purge,
+    #[serde(rename = "revert")]
+    #[sqlx(rename = "revert")]
+    Revert,
+    /// A comment has been created, updated or removed
+    #[serde(rename = "comment")]
+    #[sqlx(rename = "comment")]
+    Comment,
+    #[serde(rename = "attach")]
+    #[sqlx(rename = "attach")]
+    Attach,
+    #[serde(rename = "detach")]
+    #[sqlx(rename = "detach")]
+    Detach,
+    /// Time has been tracked, by an entry or a timer
+    #[serde(rename = "track")]
+    #[sqlx(rename = "track")]
+    Track,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+#[allow(clippy::module_name_repetitions)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub actor: String,
+    pub action: AuditAction,
+    pub todo_id: Uuid,
+    pub before: Option<Json<TodoRecord>>,
+    pub after: Option<Json<TodoRecord>>,
+    pub created_at: DateTime<Utc>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub todo_id: Option<Uuid>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Who is performing the mutation, extracted from the request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Auditor {
+    pub actor: String,
+    pub request_id: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Auditor
+where
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let user = parts
+            .extensions
+            .get::<CurrentUser>()
+            .ok_or(StatusCode::UNAUTHORIZED)?;
+        let request_id = parts
+            .extensions
+            .get::<RequestId>()
+            .and_then(|request_id| request_id.header_value().to_str().ok())
+            .map(ToString::to_string);
+
+        Ok(Self {
+            actor: user.username.clone(),
+            request_id,
+        })
+    }
+}
+
+/// Records a change made on a todo, `before` being the todo prior to the change.
+/// Must be called in the same transaction as the change itself.
+pub async fn record_change(
+    conn: &mut SqliteConnection,
+    auditor: &Auditor,
+    action: AuditAction,
+    todo_id: &Uuid,
+    before: Option<TodoRecord>,
+) -> Result<()> {
+    let after = todos::select_todo_record(&mut *conn, todo_id).await?;
+
+    sqlx::query("INSERT INTO audit_log (actor, action, todo_id, before, after, created_at, request_id) VALUES (?, ?, ?, ?, ?, ?, ?)")
+        .bind(&auditor.actor)
+        .bind(action)
+        .bind(todo_id)
+        .bind(before.map(Json))
+        .bind(after.map(Json))
+        .bind(Utc::now())
+        .bind(&auditor.request_id)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn select_audit_entries(
+    conn: &mut SqliteConnection,
+    filter: &AuditFilter,
+) -> Result<Vec<AuditEntry>> {
+    let mut query = QueryBuilder::<Sqlite>::new(
+        "SELECT id, actor, action, todo_id, before, after, created_at, request_id FROM audit_log WHERE 1 = 1",
+    );
+    if let Some(actor) = &filter.actor {
+        query.push(" AND actor = ").push_bind(actor);
+    }
+    if let Some(todo_id) = &filter.todo_id {
+        query.push(" AND todo_id = ").push_bind(todo_id);
+    }
+    if let Some(since) = &filter.since {
+        query
+            .push(" AND julianday(created_at) >= julianday(")
+            .push_bind(since)
+            .push(")");
+    }
+    if let Some(until) = &filter.until {
+        query
+            .push(" AND julianday(created_at) < julianday(")
+            .push_bind(until)
+            .push(")");
+    }
+    query.push(" ORDER BY id");
+
+    let entries = query.build_query_as().fetch_all(conn).await?;
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::DatabaseConfig, db};
+
+    #[tokio::test]
+    async fn reads_back_the_snapshots_recorded_before_the_todos_gained_fields() {
+        let config = DatabaseConfig {
+            path: ":memory:".into(),
+            max_connections: 1,
+        };
+        let pool = db::create_db_pool(&config).await.unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        let todo_id = Uuid::new_v4();
+        // As recorded when the todos only had a content and a completion
+        sqlx::query("INSERT INTO audit_log (actor, action, todo_id, before, after, created_at) VALUES (?, ?, ?, ?, ?, ?)")
+            .bind("admin")
+            .bind(AuditAction::Update)
+            .bind(todo_id)
+            .bind(format!(r#"{{"id":"{todo_id}","content":"before","completed":false,"deleted_at":null}}"#))
+            .bind(format!(r#"{{"id":"{todo_id}","content":"after","completed":true,"deleted_at":null}}"#))
+            .bind(Utc::now())
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+
+        let entries = select_audit_entries(&mut conn, &AuditFilter::default())
+            .await
+            .unwrap();
+
+        let [entry] = entries.as_slice() else {
+            panic!("expected a single entry, got {entries:?}");
+        };
+        let before = &entry.before.as_ref().unwrap().todo;
+        let after = &entry.after.as_ref().unwrap().todo;
+        assert_eq!(
+            (before.content.as_str(), before.completed),
+            ("before", false)
+        );
+        assert_eq!((after.content.as_str(), after.completed), ("after", true));
+        assert!(after.tags.is_empty());
+        assert_eq!(after.priority, None);
+    }
+}
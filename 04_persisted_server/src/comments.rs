@@ -0,0 +1,121 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, SqliteConnection};
+use uuid::Uuid;
+
+use crate::{
+    errors::{Error, Result},
+    todos,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct Comment {
+    pub id: Uuid,
+    pub todo_id: Uuid,
+    pub author: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    /// Only set once the comment has been edited
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Oldest first, the comments of a todo in the trash can't be accessed
+pub async fn select_comments(conn: &mut SqliteConnection, todo_id: &Uuid) -> Result<Vec<Comment>> {
+    if !todos::is_live(&mut *conn, todo_id).await? {
+        return Err(Error::NotFound);
+    }
+
+    let comments = sqlx::query_as("SELECT id, todo_id, author, content, created_at, updated_at FROM comments WHERE todo_id = ? ORDER BY julianday(created_at)")
+        .bind(todo_id)
+        .fetch_all(conn)
+        .await?;
+
+    Ok(comments)
+}
+
+pub async fn create_comment(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+    author: &str,
+    content: &str,
+) -> Result<Uuid> {
+    if !todos::is_live(&mut *conn, todo_id).await? {
+        return Err(Error::NotFound);
+    }
+
+    let comment_id = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO comments (id, todo_id, author, content, created_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(comment_id)
+    .bind(todo_id)
+    .bind(author)
+    .bind(content)
+    .bind(Utc::now())
+    .execute(conn)
+    .await?;
+
+    Ok(comment_id)
+}
+
+pub async fn update_comment(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+    comment_id: &Uuid,
+    author: &str,
+    content: &str,
+) -> Result<()> {
+    check_author(&mut *conn, todo_id, comment_id, author).await?;
+
+    sqlx::query("UPDATE comments SET content = ?, updated_at = ? WHERE id = ?")
+        .bind(content)
+        .bind(Utc::now())
+        .bind(comment_id)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn remove_comment(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+    comment_id: &Uuid,
+    author: &str,
+) -> Result<()> {
+    check_author(&mut *conn, todo_id, comment_id, author).await?;
+
+    sqlx::query("DELETE FROM comments WHERE id = ?")
+        .bind(comment_id)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Only the author of a comment can edit or remove it
+async fn check_author(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+    comment_id: &Uuid,
+    author: &str,
+) -> Result<()> {
+    if !todos::is_live(&mut *conn, todo_id).await? {
+        return Err(Error::NotFound);
+    }
+
+    let comment_author: Option<String> =
+        sqlx::query_scalar("SELECT author FROM comments WHERE id = ? AND todo_id = ?")
+            .bind(comment_id)
+            .bind(todo_id)
+            .fetch_optional(conn)
+            .await?;
+
+    match comment_author {
+        None => Err(Error::NotFound),
+        Some(comment_author) if comment_author != author => Err(Error::Forbidden(format!(
+            "only {comment_author} can change the comment {comment_id}"
+        ))),
+        Some(_) => Ok(()),
+    }
+}
@@ -0,0 +1,930 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Result};
+use axum::http::{HeaderName, HeaderValue};
+use clap::{Parser, ValueEnum};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::EnvFilter;
+
+use crate::{
+    attachments::{
+        DEFAULT_ATTACHMENTS_DIR, DEFAULT_ATTACHMENT_MAX_SIZE, DEFAULT_ATTACHMENT_TIMEOUT_SECS,
+    },
+    workflow::{Workflow, DEFAULT_WORKFLOW},
+};
+
+/// Read when no configuration file is given, if it exists
+static DEFAULT_CONFIG_FILE: &str = "todos.toml";
+
+static DEFAULT_BIND: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 8000));
+
+static DEFAULT_UNIX_SOCKET_PATH: &str = "todos.sock";
+
+/// Octal, readable and writable by the owner and the group, such as a reverse proxy's
+static DEFAULT_UNIX_SOCKET_MODE: &str = "660";
+
+static DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 3;
+
+/// How long the requests keep being accepted once the server isn't ready anymore, long enough for
+/// most load balancers to stop routing to it
+static DEFAULT_SHUTDOWN_DELAY_SECS: u64 = 5;
+
+/// How long the in-flight requests are given to complete on shutdown
+static DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
+
+static DEFAULT_DB_PATH: &str = "todos.db";
+
+static DEFAULT_DB_MAX_CONNECTIONS: u32 = 20;
+
+/// Number of days a todo stays in the trash before being purged
+static DEFAULT_TRASH_RETENTION_DAYS: u64 = 30;
+
+/// See the `EnvFilter` directives of `tracing_subscriber`
+static DEFAULT_LOG_FILTER: &str = "info";
+
+static DEFAULT_READS_BUDGET: BudgetConfig = BudgetConfig {
+    burst: 120,
+    per_minute: 600,
+};
+
+static DEFAULT_WRITES_BUDGET: BudgetConfig = BudgetConfig {
+    burst: 30,
+    per_minute: 120,
+};
+
+/// Delay imposed after the first failed authentication, doubled after each following one
+static DEFAULT_LOGIN_BACKOFF_SECS: u64 = 1;
+
+static DEFAULT_LOGIN_MAX_BACKOFF_SECS: u64 = 60;
+
+/// Number of consecutive failed authentications locking a username or address out
+static DEFAULT_LOGIN_LOCKOUT_THRESHOLD: u32 = 10;
+
+static DEFAULT_LOGIN_LOCKOUT_SECS: u64 = 15 * 60;
+
+static DEFAULT_LOGIN_FAILURES_TTL_SECS: u64 = 60 * 60;
+
+/// Bounds the memory taken by the failures of made up usernames
+static DEFAULT_LOGIN_MAX_SUBJECTS: usize = 10_000;
+
+/// Keeps the lockout dates representable
+static MAX_LOGIN_DELAY_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// Identifies the server in the exported traces
+static DEFAULT_OTEL_SERVICE_NAME: &str = "todos";
+
+/// The flags override the environment variables, which override the configuration file
+#[derive(Debug, Parser)]
+#[command(version, about = "A todos server persisted with SQLite")]
+pub struct Cli {
+    /// TOML configuration file, `todos.toml` is read if it exists
+    #[arg(long, env = "TODOS_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Prints the resulting configuration as TOML and exits
+    #[arg(long)]
+    pub print_config: bool,
+    #[arg(long, env = "TODOS_LISTENER")]
+    pub listener: Option<ListenerKind>,
+    #[arg(long, env = "TODOS_BIND")]
+    pub bind: Option<SocketAddr>,
+    #[arg(long, env = "TODOS_UNIX_SOCKET_PATH")]
+    pub unix_socket_path: Option<PathBuf>,
+    /// Octal permissions, such as `660`
+    #[arg(long, env = "TODOS_UNIX_SOCKET_MODE")]
+    pub unix_socket_mode: Option<String>,
+    #[arg(long, env = "TODOS_REQUEST_TIMEOUT_SECS")]
+    pub request_timeout_secs: Option<u64>,
+    #[arg(long, env = "TODOS_SHUTDOWN_DELAY_SECS")]
+    pub shutdown_delay_secs: Option<u64>,
+    #[arg(long, env = "TODOS_SHUTDOWN_TIMEOUT_SECS")]
+    pub shutdown_timeout_secs: Option<u64>,
+    /// Such as `x-forwarded-for`, only behind a reverse proxy setting it
+    #[arg(long, env = "TODOS_CLIENT_ADDRESS_HEADER")]
+    pub client_address_header: Option<String>,
+    /// Comma separated, `*` allows any origin
+    #[arg(long, env = "TODOS_CORS_ORIGINS", value_delimiter = ',')]
+    pub cors_origins: Option<Vec<String>>,
+    /// PEM certificate chain, HTTPS is served when set
+    #[arg(long, env = "TODOS_TLS_CERT_PATH")]
+    pub tls_cert_path: Option<PathBuf>,
+    /// PEM private key
+    #[arg(long, env = "TODOS_TLS_KEY_PATH")]
+    pub tls_key_path: Option<PathBuf>,
+    /// Redirects plain HTTP to HTTPS from this address
+    #[arg(long, env = "TODOS_TLS_REDIRECT_BIND")]
+    pub tls_redirect_bind: Option<SocketAddr>,
+    #[arg(long, env = "TODOS_DB_PATH")]
+    pub db_path: Option<PathBuf>,
+    #[arg(long, env = "TODOS_DB_MAX_CONNECTIONS")]
+    pub db_max_connections: Option<u32>,
+    #[arg(long, env = "TODOS_TRASH_RETENTION_DAYS")]
+    pub trash_retention_days: Option<u64>,
+    /// Comma separated urls the reminders are delivered to
+    #[arg(long, env = "TODOS_REMINDER_WEBHOOKS", value_delimiter = ',')]
+    pub reminder_webhooks: Option<Vec<String>>,
+    #[arg(long, env = "TODOS_ATTACHMENTS_DIR")]
+    pub attachments_dir: Option<PathBuf>,
+    /// In bytes
+    #[arg(long, env = "TODOS_ATTACHMENT_MAX_SIZE")]
+    pub attachment_max_size: Option<u64>,
+    #[arg(long, env = "TODOS_ATTACHMENT_TIMEOUT_SECS")]
+    pub attachment_timeout_secs: Option<u64>,
+    #[arg(long, env = "TODOS_WORKFLOW")]
+    pub workflow: Option<String>,
+    #[arg(long, env = "TODOS_LOG_FORMAT")]
+    pub log_format: Option<LogFormat>,
+    /// Directives such as `info,persisted_server=debug`
+    #[arg(long, env = "RUST_LOG")]
+    pub log_filter: Option<String>,
+    /// Base url of the OTLP collector the spans are exported to, requires the `otel` feature
+    #[arg(long, env = "OTEL_EXPORTER_OTLP_ENDPOINT")]
+    pub otel_endpoint: Option<String>,
+    #[arg(long, env = "OTEL_EXPORTER_OTLP_PROTOCOL")]
+    pub otel_protocol: Option<OtlpProtocol>,
+    #[arg(long, env = "OTEL_SERVICE_NAME")]
+    pub otel_service_name: Option<String>,
+}
+
+/// The settings missing from the configuration file keep their default value
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// See `DEFAULT_WORKFLOW` for the syntax
+    pub workflow: String,
+    pub server: ServerConfig,
+    pub tls: TlsConfig,
+    pub database: DatabaseConfig,
+    pub trash: TrashConfig,
+    pub reminders: RemindersConfig,
+    pub attachments: AttachmentsConfig,
+    pub logging: LoggingConfig,
+    pub otel: OtelConfig,
+    pub rate_limit: RateLimitConfig,
+    pub login: LoginConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listener: ListenerKind,
+    /// Listened to by the `tcp` listener
+    pub bind: SocketAddr,
+    /// Listened to by the `unix` listener, a stale socket left there is replaced
+    pub unix_socket_path: PathBuf,
+    /// Octal permissions of the Unix socket
+    pub unix_socket_mode: String,
+    pub request_timeout_secs: u64,
+    /// The in-flight requests still running after this delay are cut off on shutdown
+    pub shutdown_timeout_secs: u64,
+    /// The server keeps accepting requests for this delay after reporting it's not ready anymore,
+    /// leaving time for the load balancers to notice
+    pub shutdown_delay_secs: u64,
+    /// The header a reverse proxy sets to the address of the client, such as `x-forwarded-for`,
+    /// its last address being used. The clients can forge it, so it's only to be set behind a
+    /// proxy, otherwise the address of the peer is used.
+    pub client_address_header: Option<String>,
+    /// `*` allows any origin
+    pub cors_origins: Vec<String>,
+}
+
+/// Where the connections are accepted from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum ListenerKind {
+    /// `server.bind`
+    #[default]
+    #[serde(rename = "tcp")]
+    Tcp,
+    /// `server.unix_socket_path`
+    #[serde(rename = "unix")]
+    Unix,
+    /// The socket passed by systemd socket activation, either TCP or Unix
+    #[serde(rename = "systemd")]
+    Systemd,
+}
+
+/// HTTPS is served when a certificate is set, its files are reloaded when modified
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM certificate chain, starting with the certificate of the server
+    pub cert_path: Option<PathBuf>,
+    /// PEM private key
+    pub key_path: Option<PathBuf>,
+    /// Plain HTTP is also listened to on this address, to redirect the requests to HTTPS
+    pub redirect_bind: Option<SocketAddr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub path: PathBuf,
+    pub max_connections: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrashConfig {
+    pub retention_days: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RemindersConfig {
+    pub webhooks: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AttachmentsConfig {
+    pub dir: PathBuf,
+    /// In bytes
+    pub max_size: u64,
+    /// Replaces `server.request_timeout_secs` for the uploads and the downloads
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+    pub filter: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable
+    #[default]
+    #[serde(rename = "text")]
+    Text,
+    /// One JSON object per line, along with the fields of the current spans
+    #[serde(rename = "json")]
+    Json,
+}
+
+/// Each authenticated user has their own budgets, and each client address has its own for the
+/// requests that aren't authenticated. The peers of a Unix socket have no address, unless
+/// `server.client_address_header` is set, they aren't limited until authenticated.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// For the `GET`, `HEAD` and `OPTIONS` requests
+    pub reads: BudgetConfig,
+    pub writes: BudgetConfig,
+}
+
+/// A token bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BudgetConfig {
+    /// Number of requests allowed at once
+    pub burst: u32,
+    /// Number of requests allowed per minute in the long run
+    pub per_minute: u32,
+}
+
+/// The failed authentications are tracked per username and per client address
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoginConfig {
+    /// The next attempt is refused during this delay after the first failure, doubled after each
+    /// following one
+    pub backoff_secs: u64,
+    pub max_backoff_secs: u64,
+    /// Number of consecutive failures locking the username or address out
+    pub lockout_threshold: u32,
+    pub lockout_secs: u64,
+    /// The failures are forgotten after this delay without new ones
+    pub failures_ttl_secs: u64,
+    /// The clients sharing an address, such as the users of a reverse proxy that doesn't set
+    /// `server.client_address_header`, would lock each other out
+    pub track_addresses: bool,
+    /// Number of usernames and addresses whose failures are kept, the least recently failed ones
+    /// are forgotten first
+    pub max_subjects: usize,
+}
+
+/// The spans are only exported when an endpoint is set
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OtelConfig {
+    /// Base url of the collector, `/v1/traces` is appended for the HTTP protocols
+    pub endpoint: Option<String>,
+    pub protocol: OtlpProtocol,
+    pub service_name: String,
+}
+
+/// Named as in the `OTEL_EXPORTER_OTLP_PROTOCOL` specification
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum OtlpProtocol {
+    #[default]
+    #[serde(rename = "grpc")]
+    #[value(name = "grpc")]
+    Grpc,
+    #[serde(rename = "http/protobuf")]
+    #[value(name = "http/protobuf")]
+    HttpProtobuf,
+    #[serde(rename = "http/json")]
+    #[value(name = "http/json")]
+    HttpJson,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            workflow: DEFAULT_WORKFLOW.to_string(),
+            server: ServerConfig::default(),
+            tls: TlsConfig::default(),
+            database: DatabaseConfig::default(),
+            trash: TrashConfig::default(),
+            reminders: RemindersConfig::default(),
+            attachments: AttachmentsConfig::default(),
+            logging: LoggingConfig::default(),
+            otel: OtelConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            login: LoginConfig::default(),
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listener: ListenerKind::default(),
+            bind: DEFAULT_BIND,
+            unix_socket_path: DEFAULT_UNIX_SOCKET_PATH.into(),
+            unix_socket_mode: DEFAULT_UNIX_SOCKET_MODE.to_string(),
+            request_timeout_secs: DEFAULT_REQUEST_TIMEOUT_SECS,
+            shutdown_timeout_secs: DEFAULT_SHUTDOWN_TIMEOUT_SECS,
+            shutdown_delay_secs: DEFAULT_SHUTDOWN_DELAY_SECS,
+            client_address_header: None,
+            cors_origins: vec!["*".to_string()],
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Parses `unix_socket_mode`, `None` when it's not valid
+    pub fn unix_socket_permissions(&self) -> Option<u32> {
+        u32::from_str_radix(&self.unix_socket_mode, 8)
+            .ok()
+            .filter(|mode| *mode <= 0o777)
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            path: DEFAULT_DB_PATH.into(),
+            max_connections: DEFAULT_DB_MAX_CONNECTIONS,
+        }
+    }
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self {
+            retention_days: DEFAULT_TRASH_RETENTION_DAYS,
+        }
+    }
+}
+
+impl Default for AttachmentsConfig {
+    fn default() -> Self {
+        Self {
+            dir: DEFAULT_ATTACHMENTS_DIR.into(),
+            max_size: DEFAULT_ATTACHMENT_MAX_SIZE,
+            timeout_secs: DEFAULT_ATTACHMENT_TIMEOUT_SECS,
+        }
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::default(),
+            filter: DEFAULT_LOG_FILTER.to_string(),
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            reads: DEFAULT_READS_BUDGET,
+            writes: DEFAULT_WRITES_BUDGET,
+        }
+    }
+}
+
+impl Default for LoginConfig {
+    fn default() -> Self {
+        Self {
+            backoff_secs: DEFAULT_LOGIN_BACKOFF_SECS,
+            max_backoff_secs: DEFAULT_LOGIN_MAX_BACKOFF_SECS,
+            lockout_threshold: DEFAULT_LOGIN_LOCKOUT_THRESHOLD,
+            lockout_secs: DEFAULT_LOGIN_LOCKOUT_SECS,
+            failures_ttl_secs: DEFAULT_LOGIN_FAILURES_TTL_SECS,
+            track_addresses: true,
+            max_subjects: DEFAULT_LOGIN_MAX_SUBJECTS,
+        }
+    }
+}
+
+impl Default for OtelConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            protocol: OtlpProtocol::default(),
+            service_name: DEFAULT_OTEL_SERVICE_NAME.to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// Layers the defaults, the configuration file, and the environment variables and flags,
+    /// then validates the result
+    pub fn load(cli: &Cli) -> Result<Self> {
+        let mut config = match &cli.config {
+            Some(path) => Self::read(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::read(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Self::default(),
+        };
+        config.apply(cli);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("can't read {}: {err}", path.display()))?;
+
+        toml::from_str(&content).map_err(|err| anyhow!("invalid {}: {err}", path.display()))
+    }
+
+    fn apply(&mut self, cli: &Cli) {
+        if let Some(workflow) = &cli.workflow {
+            self.workflow.clone_from(workflow);
+        }
+        if let Some(log_format) = cli.log_format {
+            self.logging.format = log_format;
+        }
+        if let Some(log_filter) = &cli.log_filter {
+            self.logging.filter.clone_from(log_filter);
+        }
+        if let Some(otel_endpoint) = &cli.otel_endpoint {
+            self.otel.endpoint = Some(otel_endpoint.clone());
+        }
+        if let Some(otel_protocol) = cli.otel_protocol {
+            self.otel.protocol = otel_protocol;
+        }
+        if let Some(otel_service_name) = &cli.otel_service_name {
+            self.otel.service_name.clone_from(otel_service_name);
+        }
+        if let Some(listener) = cli.listener {
+            self.server.listener = listener;
+        }
+        if let Some(bind) = cli.bind {
+            self.server.bind = bind;
+        }
+        if let Some(unix_socket_path) = &cli.unix_socket_path {
+            self.server.unix_socket_path.clone_from(unix_socket_path);
+        }
+        if let Some(unix_socket_mode) = &cli.unix_socket_mode {
+            self.server.unix_socket_mode.clone_from(unix_socket_mode);
+        }
+        if let Some(request_timeout_secs) = cli.request_timeout_secs {
+            self.server.request_timeout_secs = request_timeout_secs;
+        }
+        if let Some(shutdown_delay_secs) = cli.shutdown_delay_secs {
+            self.server.shutdown_delay_secs = shutdown_delay_secs;
+        }
+        if let Some(shutdown_timeout_secs) = cli.shutdown_timeout_secs {
+            self.server.shutdown_timeout_secs = shutdown_timeout_secs;
+        }
+        if let Some(client_address_header) = &cli.client_address_header {
+            self.server.client_address_header = Some(client_address_header.clone());
+        }
+        if let Some(cors_origins) = &cli.cors_origins {
+            self.server.cors_origins.clone_from(cors_origins);
+        }
+        if let Some(tls_cert_path) = &cli.tls_cert_path {
+            self.tls.cert_path = Some(tls_cert_path.clone());
+        }
+        if let Some(tls_key_path) = &cli.tls_key_path {
+            self.tls.key_path = Some(tls_key_path.clone());
+        }
+        if let Some(tls_redirect_bind) = cli.tls_redirect_bind {
+            self.tls.redirect_bind = Some(tls_redirect_bind);
+        }
+        if let Some(db_path) = &cli.db_path {
+            self.database.path.clone_from(db_path);
+        }
+        if let Some(db_max_connections) = cli.db_max_connections {
+            self.database.max_connections = db_max_connections;
+        }
+        if let Some(trash_retention_days) = cli.trash_retention_days {
+            self.trash.retention_days = trash_retention_days;
+        }
+        if let Some(reminder_webhooks) = &cli.reminder_webhooks {
+            self.reminders.webhooks = reminder_webhooks
+                .iter()
+                .map(|url| url.trim().to_string())
+                .collect();
+        }
+        if let Some(attachments_dir) = &cli.attachments_dir {
+            self.attachments.dir.clone_from(attachments_dir);
+        }
+        if let Some(attachment_max_size) = cli.attachment_max_size {
+            self.attachments.max_size = attachment_max_size;
+        }
+        if let Some(attachment_timeout_secs) = cli.attachment_timeout_secs {
+            self.attachments.timeout_secs = attachment_timeout_secs;
+        }
+    }
+
+    fn validate_tls(&self, errors: &mut Vec<String>) {
+        match (&self.tls.cert_path, &self.tls.key_path) {
+            (Some(_), None) => errors.push("tls.key_path: required with tls.cert_path".to_string()),
+            (None, Some(_)) => errors.push("tls.cert_path: required with tls.key_path".to_string()),
+            _ => {}
+        }
+        if let Some(redirect_bind) = self.tls.redirect_bind {
+            if self.tls.cert_path.is_none() {
+                errors.push("tls.redirect_bind: requires tls.cert_path".to_string());
+            }
+            match self.server.listener {
+                ListenerKind::Tcp if redirect_bind == self.server.bind => {
+                    errors.push("tls.redirect_bind: must differ from server.bind".to_string());
+                }
+                ListenerKind::Unix => {
+                    errors.push("tls.redirect_bind: requires a TCP listener".to_string());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn validate_login(&self, errors: &mut Vec<String>) {
+        if self.login.backoff_secs > self.login.max_backoff_secs {
+            errors.push("login.backoff_secs: can't exceed login.max_backoff_secs".to_string());
+        }
+        if self.login.lockout_threshold == 0 {
+            errors.push("login.lockout_threshold: must be at least 1".to_string());
+        }
+        if self.login.max_subjects == 0 {
+            errors.push("login.max_subjects: must be at least 1".to_string());
+        }
+        for (name, secs) in [
+            ("max_backoff_secs", self.login.max_backoff_secs),
+            ("lockout_secs", self.login.lockout_secs),
+            ("failures_ttl_secs", self.login.failures_ttl_secs),
+        ] {
+            if secs > MAX_LOGIN_DELAY_SECS {
+                errors.push(format!("login.{name}: can't exceed a year"));
+            }
+        }
+    }
+
+    /// Reports all the invalid settings at once
+    fn validate(&self) -> Result<()> {
+        let mut errors = Vec::new();
+
+        if let Err(err) = self.workflow.parse::<Workflow>() {
+            errors.push(format!("workflow: {err}"));
+        }
+        if self.server.request_timeout_secs == 0 {
+            errors.push("server.request_timeout_secs: must be at least 1".to_string());
+        }
+        if self.server.unix_socket_permissions().is_none() {
+            errors.push(format!(
+                "server.unix_socket_mode: invalid octal permissions {}",
+                self.server.unix_socket_mode
+            ));
+        }
+        if let Some(header) = &self.server.client_address_header {
+            if HeaderName::from_str(header).is_err() {
+                errors.push(format!(
+                    "server.client_address_header: invalid header {header}"
+                ));
+            }
+        }
+        if self.server.cors_origins.iter().any(|origin| origin == "*") {
+            if self.server.cors_origins.len() > 1 {
+                errors.push("server.cors_origins: `*` can't be combined with origins".to_string());
+            }
+        } else {
+            for origin in &self.server.cors_origins {
+                if HeaderValue::from_str(origin).is_err() || Url::parse(origin).is_err() {
+                    errors.push(format!("server.cors_origins: invalid origin {origin}"));
+                }
+            }
+        }
+        self.validate_tls(&mut errors);
+        if self.database.max_connections == 0 {
+            errors.push("database.max_connections: must be at least 1".to_string());
+        }
+        if self
+            .trash
+            .retention_days
+            .checked_mul(24 * 60 * 60)
+            .is_none()
+        {
+            errors.push("trash.retention_days: too large".to_string());
+        }
+        for webhook in &self.reminders.webhooks {
+            match Url::parse(webhook) {
+                Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+                _ => errors.push(format!("reminders.webhooks: invalid url {webhook}")),
+            }
+        }
+        if self.attachments.max_size == 0 {
+            errors.push("attachments.max_size: must be at least 1".to_string());
+        }
+        if self.attachments.timeout_secs == 0 {
+            errors.push("attachments.timeout_secs: must be at least 1".to_string());
+        }
+        if let Err(err) = EnvFilter::try_new(&self.logging.filter) {
+            errors.push(format!("logging.filter: {err}"));
+        }
+        for (name, budget) in [
+            ("reads", self.rate_limit.reads),
+            ("writes", self.rate_limit.writes),
+        ] {
+            if budget.burst == 0 {
+                errors.push(format!("rate_limit.{name}.burst: must be at least 1"));
+            }
+            if budget.per_minute == 0 {
+                errors.push(format!("rate_limit.{name}.per_minute: must be at least 1"));
+            }
+        }
+        self.validate_login(&mut errors);
+        if let Some(endpoint) = &self.otel.endpoint {
+            if !cfg!(feature = "otel") {
+                errors.push(
+                    "otel.endpoint: the server is built without the `otel` feature".to_string(),
+                );
+            }
+            match Url::parse(endpoint) {
+                Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+                _ => errors.push(format!("otel.endpoint: invalid url {endpoint}")),
+            }
+        }
+
+        if !errors.is_empty() {
+            bail!("invalid configuration:\n  {}", errors.join("\n  "));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, ffi::OsStr, fs};
+
+    use clap::{CommandFactory, FromArgMatches};
+    use uuid::Uuid;
+
+    use super::*;
+
+    /// Asserts that the configuration is rejected with the error
+    fn assert_invalid(config: &Config, error: &str) {
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains(error), "expected {error:?} in {err:?}");
+    }
+
+    /// Parses the flags like `Cli::parse`, reading the environment variables from `vars` rather
+    /// than from the environment of the process, which the tests running in parallel share
+    fn parse_with_env(args: &[&str], vars: &[(&str, &'static str)]) -> Cli {
+        let command = Cli::command().mut_args(|arg| {
+            let value = vars
+                .iter()
+                .find(|(name, _)| arg.get_env() == Some(OsStr::new(name)))
+                .map(|(_, value)| *value);
+            // A default value is overridden by the flag, like an environment variable
+            let arg = arg.env(None);
+            match value {
+                Some(value) => arg.default_value(value),
+                None => arg,
+            }
+        });
+
+        Cli::from_arg_matches(&command.try_get_matches_from(args).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn layers_the_file_then_the_environment_then_the_flags() {
+        let path = env::temp_dir().join(format!("{}.toml", Uuid::new_v4()));
+        fs::write(
+            &path,
+            "workflow = \"todo>done\"\n\
+             [server]\nrequest_timeout_secs = 10\nshutdown_delay_secs = 1\n\
+             [database]\nmax_connections = 5\n",
+        )
+        .unwrap();
+        let cli = parse_with_env(
+            &[
+                "persisted-server",
+                "--config",
+                path.to_str().unwrap(),
+                "--db-max-connections",
+                "9",
+            ],
+            &[
+                ("TODOS_SHUTDOWN_DELAY_SECS", "2"),
+                ("TODOS_DB_MAX_CONNECTIONS", "7"),
+            ],
+        );
+
+        let config = Config::load(&cli);
+        fs::remove_file(&path).unwrap();
+
+        let config = config.unwrap();
+        assert_eq!(config.workflow, "todo>done");
+        assert_eq!(config.server.request_timeout_secs, 10);
+        assert_eq!(config.server.shutdown_delay_secs, 2);
+        assert_eq!(config.database.max_connections, 9);
+        assert_eq!(config.database.path, Path::new(DEFAULT_DB_PATH));
+    }
+
+    #[test]
+    fn rejects_the_unknown_settings() {
+        let path = env::temp_dir().join(format!("{}.toml", Uuid::new_v4()));
+        fs::write(&path, "[server]\nrequest_timeout = 10\n").unwrap();
+
+        let config = Config::read(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn accepts_the_defaults() {
+        Config::default().validate().unwrap();
+    }
+
+    #[test]
+    fn reports_all_the_invalid_settings_at_once() {
+        let mut config = Config::default();
+        config.server.request_timeout_secs = 0;
+        config.database.max_connections = 0;
+
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("server.request_timeout_secs"), "{err}");
+        assert!(err.contains("database.max_connections"), "{err}");
+    }
+
+    #[test]
+    fn rejects_the_invalid_server_settings() {
+        let config = Config {
+            workflow: "todo>".to_string(),
+            ..Config::default()
+        };
+        assert_invalid(&config, "workflow: ");
+
+        let mut config = Config::default();
+        config.server.request_timeout_secs = 0;
+        assert_invalid(&config, "server.request_timeout_secs: must be at least 1");
+
+        let mut config = Config::default();
+        config.server.unix_socket_mode = "999".to_string();
+        assert_invalid(
+            &config,
+            "server.unix_socket_mode: invalid octal permissions 999",
+        );
+
+        let mut config = Config::default();
+        config.server.cors_origins = vec!["*".to_string(), "https://example.com".to_string()];
+        assert_invalid(
+            &config,
+            "server.cors_origins: `*` can't be combined with origins",
+        );
+
+        let mut config = Config::default();
+        config.server.cors_origins = vec!["not an origin".to_string()];
+        assert_invalid(&config, "server.cors_origins: invalid origin not an origin");
+
+        let mut config = Config::default();
+        config.server.client_address_header = Some("x forwarded".to_string());
+        assert_invalid(
+            &config,
+            "server.client_address_header: invalid header x forwarded",
+        );
+    }
+
+    #[test]
+    fn rejects_the_invalid_tls_settings() {
+        let mut config = Config::default();
+        config.tls.cert_path = Some("cert.pem".into());
+        assert_invalid(&config, "tls.key_path: required with tls.cert_path");
+
+        let mut config = Config::default();
+        config.tls.key_path = Some("key.pem".into());
+        assert_invalid(&config, "tls.cert_path: required with tls.key_path");
+
+        let mut config = Config::default();
+        config.tls.redirect_bind = Some("127.0.0.1:8080".parse().unwrap());
+        assert_invalid(&config, "tls.redirect_bind: requires tls.cert_path");
+
+        let mut config = Config::default();
+        config.tls.redirect_bind = Some(config.server.bind);
+        assert_invalid(&config, "tls.redirect_bind: must differ from server.bind");
+
+        let mut config = Config::default();
+        config.server.listener = ListenerKind::Unix;
+        config.tls.redirect_bind = Some("127.0.0.1:8080".parse().unwrap());
+        assert_invalid(&config, "tls.redirect_bind: requires a TCP listener");
+    }
+
+    #[test]
+    fn rejects_the_invalid_storage_settings() {
+        let mut config = Config::default();
+        config.database.max_connections = 0;
+        assert_invalid(&config, "database.max_connections: must be at least 1");
+
+        let mut config = Config::default();
+        config.trash.retention_days = u64::MAX;
+        assert_invalid(&config, "trash.retention_days: too large");
+
+        let mut config = Config::default();
+        config.reminders.webhooks = vec!["ftp://example.com".to_string()];
+        assert_invalid(&config, "reminders.webhooks: invalid url ftp://example.com");
+
+        let mut config = Config::default();
+        config.attachments.max_size = 0;
+        assert_invalid(&config, "attachments.max_size: must be at least 1");
+
+        let mut config = Config::default();
+        config.attachments.timeout_secs = 0;
+        assert_invalid(&config, "attachments.timeout_secs: must be at least 1");
+    }
+
+    #[test]
+    fn rejects_the_invalid_logging_settings() {
+        let mut config = Config::default();
+        config.logging.filter = "info,[".to_string();
+        assert_invalid(&config, "logging.filter: ");
+
+        let mut config = Config::default();
+        config.otel.endpoint = Some("collector:4317".to_string());
+        assert_invalid(&config, "otel.endpoint: invalid url collector:4317");
+
+        let mut config = Config::default();
+        config.otel.endpoint = Some("http://collector:4317".to_string());
+        if cfg!(feature = "otel") {
+            config.validate().unwrap();
+        } else {
+            assert_invalid(
+                &config,
+                "otel.endpoint: the server is built without the `otel` feature",
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_the_invalid_rate_limit_settings() {
+        let mut config = Config::default();
+        config.rate_limit.reads.burst = 0;
+        assert_invalid(&config, "rate_limit.reads.burst: must be at least 1");
+
+        let mut config = Config::default();
+        config.rate_limit.writes.per_minute = 0;
+        assert_invalid(&config, "rate_limit.writes.per_minute: must be at least 1");
+    }
+
+    #[test]
+    fn rejects_the_invalid_login_settings() {
+        let mut config = Config::default();
+        config.login.backoff_secs = config.login.max_backoff_secs + 1;
+        assert_invalid(
+            &config,
+            "login.backoff_secs: can't exceed login.max_backoff_secs",
+        );
+
+        let mut config = Config::default();
+        config.login.lockout_threshold = 0;
+        assert_invalid(&config, "login.lockout_threshold: must be at least 1");
+
+        let mut config = Config::default();
+        config.login.max_subjects = 0;
+        assert_invalid(&config, "login.max_subjects: must be at least 1");
+
+        let mut config = Config::default();
+        config.login.lockout_secs = MAX_LOGIN_DELAY_SECS + 1;
+        assert_invalid(&config, "login.lockout_secs: can't exceed a year");
+
+        let mut config = Config::default();
+        config.login.failures_ttl_secs = MAX_LOGIN_DELAY_SECS + 1;
+        assert_invalid(&config, "login.failures_ttl_secs: can't exceed a year");
+    }
+}
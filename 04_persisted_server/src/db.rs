@@ -1,19 +1,28 @@
 use std::time::Duration;
 
 use anyhow::Result;
+use chrono::Utc;
 use sqlx::{
-    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
-    Pool, Row, Sqlite, SqliteConnection,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    Pool, QueryBuilder, Row, Sqlite, SqliteConnection, Transaction,
 };
 use tracing::debug;
+use uuid::Uuid;
 
-pub async fn create_db_pool(filename: &str) -> Result<Pool<Sqlite>> {
+use crate::{config::DatabaseConfig, positions, workflow::Workflow};
+
+/// The version reached by `perform_migrations`, to bump with every new migration
+pub static MIGRATIONS_VERSION: i64 = 18;
+
+pub async fn create_db_pool(config: &DatabaseConfig) -> Result<Pool<Sqlite>> {
     let pool_opts = SqliteConnectOptions::new()
-        .filename(filename)
-        .create_if_missing(true);
+        .filename(&config.path)
+        .create_if_missing(true)
+        // The readers don't block the writer, the log is checkpointed when closing the pool
+        .journal_mode(SqliteJournalMode::Wal);
 
     let pool = SqlitePoolOptions::new()
-        .max_connections(20)
+        .max_connections(config.max_connections)
         .acquire_timeout(Duration::from_secs(1))
         .connect_with(pool_opts)
         .await?;
@@ -24,6 +33,79 @@ pub async fn create_db_pool(filename: &str) -> Result<Pool<Sqlite>> {
     Ok(pool)
 }
 
+/// Checkpoints the write-ahead log into the database file, and closes the pool once all the
+/// connections are released
+pub async fn close_db_pool(pool: Pool<Sqlite>) -> Result<()> {
+    debug!("closing the database");
+    sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+        .execute(&pool)
+        .await?;
+    pool.close().await;
+
+    Ok(())
+}
+
+/// The workflow being configurable, the todos whose state isn't part of it are moved to its initial
+/// or terminal state depending on their completion, and the completion follows the state
+pub async fn conform_todo_states(pool: &Pool<Sqlite>, workflow: &Workflow) -> Result<()> {
+    let mut conn = pool.acquire().await?;
+
+    let mut query =
+        QueryBuilder::<Sqlite>::new("UPDATE todos SET state = CASE WHEN completed = 1 THEN ");
+    query
+        .push_bind(&workflow.terminal)
+        .push(" ELSE ")
+        .push_bind(workflow.initial())
+        .push(" END WHERE state NOT IN (");
+    let mut states = query.separated(", ");
+    for state in &workflow.states {
+        states.push_bind(state);
+    }
+    states.push_unseparated(")");
+    let result = query.build().execute(&mut *conn).await?;
+    debug!(
+        "{} todo states conformed to the workflow",
+        result.rows_affected()
+    );
+
+    sqlx::query("UPDATE todos SET completed = (state = ?) WHERE completed != (state = ?)")
+        .bind(&workflow.terminal)
+        .bind(&workflow.terminal)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Begins a transaction holding the write lock from the start, as `BEGIN IMMEDIATE` would.
+/// A deferred transaction that read can't write anymore once another connection committed, it
+/// fails right away instead of waiting for the lock.
+pub async fn begin_write(pool: &Pool<Sqlite>) -> sqlx::Result<Transaction<'static, Sqlite>> {
+    let mut tx = pool.begin().await?;
+    // Any write takes the lock, even when it changes nothing
+    sqlx::query("UPDATE migrations SET version = version WHERE 0")
+        .execute(&mut *tx)
+        .await?;
+
+    Ok(tx)
+}
+
+/// A migrated database living in memory as long as the pool
+#[cfg(test)]
+pub async fn create_test_db_pool() -> Pool<Sqlite> {
+    let pool = SqlitePoolOptions::new()
+        // Every connection would open a database of its own
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    let mut conn = pool.acquire().await.unwrap();
+    perform_migrations(conn.as_mut()).await.unwrap();
+    drop(conn);
+
+    pool
+}
+
 async fn perform_migrations(conn: &mut SqliteConnection) -> Result<()> {
     debug!("performing migrations");
     sqlx::query("CREATE TABLE IF NOT EXISTS migrations (version INTEGER NOT NULL)")
@@ -42,11 +124,79 @@ async fn perform_migrations(conn: &mut SqliteConnection) -> Result<()> {
         0
     };
 
-    #[allow(unused_assignments)]
     if current_version == 0 {
         current_version = create_todos_table(&mut *conn).await?;
     }
 
+    if current_version == 1 {
+        current_version = add_todos_deleted_at(&mut *conn).await?;
+    }
+
+    if current_version == 2 {
+        current_version = create_audit_log_table(&mut *conn).await?;
+    }
+
+    if current_version == 3 {
+        current_version = create_todo_revisions_table(&mut *conn).await?;
+    }
+
+    if current_version == 4 {
+        current_version = add_todos_due_dates(&mut *conn).await?;
+    }
+
+    if current_version == 5 {
+        current_version = add_todos_priority_and_tags(&mut *conn).await?;
+    }
+
+    if current_version == 6 {
+        current_version = add_todos_parent_id(&mut *conn).await?;
+    }
+
+    if current_version == 7 {
+        current_version = create_todo_dependencies_table(&mut *conn).await?;
+    }
+
+    if current_version == 8 {
+        current_version = add_todos_recurrence(&mut *conn).await?;
+    }
+
+    if current_version == 9 {
+        current_version = add_todos_position(&mut *conn).await?;
+    }
+
+    if current_version == 10 {
+        current_version = add_todos_state(&mut *conn).await?;
+    }
+
+    if current_version == 11 {
+        current_version = create_comments_table(&mut *conn).await?;
+    }
+
+    if current_version == 12 {
+        current_version = create_attachments_table(&mut *conn).await?;
+    }
+
+    if current_version == 13 {
+        current_version = create_time_entries_table(&mut *conn).await?;
+    }
+
+    if current_version == 14 {
+        current_version = create_projects_table(&mut *conn).await?;
+    }
+
+    if current_version == 15 {
+        current_version = create_lockout_log_table(&mut *conn).await?;
+    }
+
+    if current_version == 16 {
+        current_version = add_todo_revisions_state(&mut *conn).await?;
+    }
+
+    #[allow(unused_assignments)]
+    if current_version == 17 {
+        current_version = make_todos_position_unique(&mut *conn).await?;
+    }
+
     debug!("migrations done");
     Ok(())
 }
@@ -60,3 +210,382 @@ async fn create_todos_table(conn: &mut SqliteConnection) -> Result<i64> {
 
     Ok(1)
 }
+
+async fn add_todos_deleted_at(conn: &mut SqliteConnection) -> Result<i64> {
+    debug!("adding the deleted_at column to the todos table");
+    sqlx::query("ALTER TABLE todos ADD COLUMN deleted_at TEXT")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("UPDATE migrations SET version = 2")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(2)
+}
+
+async fn create_audit_log_table(conn: &mut SqliteConnection) -> Result<i64> {
+    debug!("creating the audit_log table");
+    sqlx::query("CREATE TABLE IF NOT EXISTS audit_log (id INTEGER PRIMARY KEY AUTOINCREMENT, actor TEXT NOT NULL, action TEXT NOT NULL, todo_id BLOB NOT NULL, before TEXT, after TEXT, created_at TEXT NOT NULL, request_id TEXT)").execute(&mut *conn).await?;
+    // The audit log is append-only
+    sqlx::query("CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log BEGIN SELECT RAISE(ABORT, 'audit_log is append-only'); END").execute(&mut *conn).await?;
+    sqlx::query("CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log BEGIN SELECT RAISE(ABORT, 'audit_log is append-only'); END").execute(&mut *conn).await?;
+    sqlx::query("UPDATE migrations SET version = 3")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(3)
+}
+
+async fn create_todo_revisions_table(conn: &mut SqliteConnection) -> Result<i64> {
+    debug!("creating the todo_revisions table");
+    sqlx::query("CREATE TABLE IF NOT EXISTS todo_revisions (todo_id BLOB NOT NULL REFERENCES todos(id) ON DELETE CASCADE, revision INTEGER NOT NULL, content TEXT NOT NULL, completed BOOLEAN NOT NULL, created_at TEXT NOT NULL, PRIMARY KEY (todo_id, revision))").execute(&mut *conn).await?;
+    // The existing todos start their history with their current state
+    sqlx::query("INSERT INTO todo_revisions SELECT id, 1, content, completed, ? FROM todos")
+        .bind(Utc::now())
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("UPDATE migrations SET version = 4")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(4)
+}
+
+async fn add_todos_due_dates(conn: &mut SqliteConnection) -> Result<i64> {
+    debug!("adding the due and reminder dates to the todos table");
+    sqlx::query("ALTER TABLE todos ADD COLUMN due_at TEXT")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("ALTER TABLE todos ADD COLUMN remind_at TEXT")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("ALTER TABLE todos ADD COLUMN reminded_at TEXT")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("UPDATE migrations SET version = 5")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(5)
+}
+
+async fn add_todos_priority_and_tags(conn: &mut SqliteConnection) -> Result<i64> {
+    debug!("adding the priority and the tags to the todos");
+    sqlx::query("ALTER TABLE todos ADD COLUMN priority INTEGER")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("CREATE TABLE IF NOT EXISTS tags (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL UNIQUE)").execute(&mut *conn).await?;
+    sqlx::query("CREATE TABLE IF NOT EXISTS todo_tags (todo_id BLOB NOT NULL REFERENCES todos(id) ON DELETE CASCADE, tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE, PRIMARY KEY (todo_id, tag_id))").execute(&mut *conn).await?;
+    sqlx::query("UPDATE migrations SET version = 6")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(6)
+}
+
+async fn add_todos_parent_id(conn: &mut SqliteConnection) -> Result<i64> {
+    debug!("adding the parent_id column to the todos table");
+    sqlx::query(
+        "ALTER TABLE todos ADD COLUMN parent_id BLOB REFERENCES todos(id) ON DELETE SET NULL",
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS todos_parent_id ON todos (parent_id)")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("UPDATE migrations SET version = 7")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(7)
+}
+
+async fn create_todo_dependencies_table(conn: &mut SqliteConnection) -> Result<i64> {
+    debug!("creating the todo_dependencies table");
+    sqlx::query("CREATE TABLE IF NOT EXISTS todo_dependencies (todo_id BLOB NOT NULL REFERENCES todos(id) ON DELETE CASCADE, blocker_id BLOB NOT NULL REFERENCES todos(id) ON DELETE CASCADE, PRIMARY KEY (todo_id, blocker_id))").execute(&mut *conn).await?;
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS todo_dependencies_blocker_id ON todo_dependencies (blocker_id)",
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query("UPDATE migrations SET version = 8")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(8)
+}
+
+async fn add_todos_recurrence(conn: &mut SqliteConnection) -> Result<i64> {
+    debug!("adding the recurrence columns to the todos table");
+    sqlx::query("ALTER TABLE todos ADD COLUMN recurrence TEXT")
+        .execute(&mut *conn)
+        .await?;
+    // The rank of the todo among the occurrences of its recurrence, starting from 1
+    sqlx::query("ALTER TABLE todos ADD COLUMN occurrence INTEGER NOT NULL DEFAULT 1")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("UPDATE migrations SET version = 9")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(9)
+}
+
+async fn add_todos_position(conn: &mut SqliteConnection) -> Result<i64> {
+    debug!("adding the position column to the todos table");
+    sqlx::query("ALTER TABLE todos ADD COLUMN position TEXT NOT NULL DEFAULT ''")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS todos_position ON todos (position)")
+        .execute(&mut *conn)
+        .await?;
+    // The existing todos keep their creation order
+    let ids: Vec<Uuid> = sqlx::query_scalar("SELECT id FROM todos ORDER BY rowid")
+        .fetch_all(&mut *conn)
+        .await?;
+    let mut position = None;
+    for id in ids {
+        position = positions::key_between(position.as_deref(), None);
+        sqlx::query("UPDATE todos SET position = ? WHERE id = ?")
+            .bind(&position)
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+    }
+    sqlx::query("UPDATE migrations SET version = 10")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(10)
+}
+
+// The states are set from the completion by `conform_todo_states`
+async fn add_todos_state(conn: &mut SqliteConnection) -> Result<i64> {
+    debug!("adding the state column to the todos table");
+    sqlx::query("ALTER TABLE todos ADD COLUMN state TEXT NOT NULL DEFAULT ''")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS todos_state ON todos (state)")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("UPDATE migrations SET version = 11")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(11)
+}
+
+async fn create_comments_table(conn: &mut SqliteConnection) -> Result<i64> {
+    debug!("creating the comments table");
+    sqlx::query("CREATE TABLE IF NOT EXISTS comments (id BLOB PRIMARY KEY NOT NULL, todo_id BLOB NOT NULL REFERENCES todos(id) ON DELETE CASCADE, author TEXT NOT NULL, content TEXT NOT NULL, created_at TEXT NOT NULL, updated_at TEXT)").execute(&mut *conn).await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS comments_todo_id ON comments (todo_id)")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("UPDATE migrations SET version = 12")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(12)
+}
+
+async fn create_attachments_table(conn: &mut SqliteConnection) -> Result<i64> {
+    debug!("creating the attachments table");
+    sqlx::query("CREATE TABLE IF NOT EXISTS attachments (id BLOB PRIMARY KEY NOT NULL, todo_id BLOB NOT NULL REFERENCES todos(id) ON DELETE CASCADE, filename TEXT NOT NULL, content_type TEXT NOT NULL, size INTEGER NOT NULL, sha256 TEXT NOT NULL, created_at TEXT NOT NULL)").execute(&mut *conn).await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS attachments_todo_id ON attachments (todo_id)")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS attachments_sha256 ON attachments (sha256)")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("UPDATE migrations SET version = 13")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(13)
+}
+
+async fn create_time_entries_table(conn: &mut SqliteConnection) -> Result<i64> {
+    debug!("creating the time_entries table");
+    sqlx::query("CREATE TABLE IF NOT EXISTS time_entries (id BLOB PRIMARY KEY NOT NULL, todo_id BLOB NOT NULL REFERENCES todos(id) ON DELETE CASCADE, username TEXT NOT NULL, started_at TEXT NOT NULL, stopped_at TEXT)").execute(&mut *conn).await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS time_entries_todo_id ON time_entries (todo_id)")
+        .execute(&mut *conn)
+        .await?;
+    // A user can't have more than one running timer
+    sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS time_entries_running ON time_entries (username) WHERE stopped_at IS NULL")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("UPDATE migrations SET version = 14")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(14)
+}
+
+async fn create_projects_table(conn: &mut SqliteConnection) -> Result<i64> {
+    debug!("creating the projects table");
+    sqlx::query("CREATE TABLE IF NOT EXISTS projects (id BLOB PRIMARY KEY NOT NULL, name TEXT NOT NULL, description TEXT NOT NULL DEFAULT '', archived BOOLEAN NOT NULL DEFAULT 0, created_at TEXT NOT NULL)").execute(&mut *conn).await?;
+    sqlx::query(
+        "ALTER TABLE todos ADD COLUMN project_id BLOB REFERENCES projects(id) ON DELETE SET NULL",
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS todos_project_id ON todos (project_id)")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("UPDATE migrations SET version = 15")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(15)
+}
+
+async fn create_lockout_log_table(conn: &mut SqliteConnection) -> Result<i64> {
+    debug!("creating the lockout_log table");
+    sqlx::query("CREATE TABLE IF NOT EXISTS lockout_log (id INTEGER PRIMARY KEY AUTOINCREMENT, action TEXT NOT NULL, kind TEXT NOT NULL, subject TEXT NOT NULL, failures INTEGER, locked_until TEXT, actor TEXT, created_at TEXT NOT NULL, request_id TEXT)").execute(&mut *conn).await?;
+    // Like the audit log, the lockout log is append-only
+    sqlx::query("CREATE TRIGGER IF NOT EXISTS lockout_log_no_update BEFORE UPDATE ON lockout_log BEGIN SELECT RAISE(ABORT, 'lockout_log is append-only'); END").execute(&mut *conn).await?;
+    sqlx::query("CREATE TRIGGER IF NOT EXISTS lockout_log_no_delete BEFORE DELETE ON lockout_log BEGIN SELECT RAISE(ABORT, 'lockout_log is append-only'); END").execute(&mut *conn).await?;
+    sqlx::query("UPDATE migrations SET version = 16")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(16)
+}
+
+/// The revisions recorded before keep a `NULL` state
+async fn add_todo_revisions_state(conn: &mut SqliteConnection) -> Result<i64> {
+    debug!("adding the state column to the todo_revisions table");
+    sqlx::query("ALTER TABLE todo_revisions ADD COLUMN state TEXT")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("UPDATE migrations SET version = 17")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(17)
+}
+
+/// The todos sharing a position, which moves between non-adjacent todos could cause, are
+/// renumbered first, keeping their order
+async fn make_todos_position_unique(conn: &mut SqliteConnection) -> Result<i64> {
+    debug!("making the position of the todos unique");
+    let duplicates: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM (SELECT position FROM todos GROUP BY position HAVING COUNT(*) > 1)",
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    if duplicates > 0 {
+        let ids: Vec<Uuid> = sqlx::query_scalar("SELECT id FROM todos ORDER BY position, rowid")
+            .fetch_all(&mut *conn)
+            .await?;
+        let mut position = None;
+        for id in ids {
+            position = positions::key_between(position.as_deref(), None);
+            sqlx::query("UPDATE todos SET position = ? WHERE id = ?")
+                .bind(&position)
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+        }
+    }
+    sqlx::query("DROP INDEX IF EXISTS todos_position")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS todos_position ON todos (position)")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("UPDATE migrations SET version = 18")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(18)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use tokio::time::sleep;
+
+    use super::*;
+    use crate::{
+        payloads::{NewTodoRequest, UpdateTodoRequest},
+        todos,
+        workflow::DEFAULT_WORKFLOW,
+    };
+
+    fn new_todo(content: &str) -> NewTodoRequest {
+        NewTodoRequest {
+            content: content.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn rename(content: &str) -> UpdateTodoRequest {
+        UpdateTodoRequest {
+            content: Some(content.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn write_transactions_read_then_write_concurrently() {
+        // The write-ahead log, where the conflict happens, requires a database file
+        let path = env::temp_dir().join(format!("todos-{}.db", Uuid::new_v4()));
+        let config = DatabaseConfig {
+            path: path.clone(),
+            max_connections: 2,
+        };
+        let pool = create_db_pool(&config).await.unwrap();
+        let workflow: Workflow = DEFAULT_WORKFLOW.parse().unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        let first_id = todos::create_todo(&mut conn, &new_todo("first"), &workflow)
+            .await
+            .unwrap();
+        let second_id = todos::create_todo(&mut conn, &new_todo("second"), &workflow)
+            .await
+            .unwrap();
+        drop(conn);
+
+        // Reads before writing like the audited handlers, while the other update goes on
+        let mut first = begin_write(&pool).await.unwrap();
+        todos::select_todo_record(&mut first, &first_id)
+            .await
+            .unwrap();
+        let second = tokio::spawn({
+            let pool = pool.clone();
+            let workflow = workflow.clone();
+            async move {
+                let mut second = begin_write(&pool).await?;
+                todos::select_todo_record(&mut second, &second_id).await?;
+                todos::update_todo(
+                    &mut second,
+                    &second_id,
+                    &rename("second, renamed"),
+                    &workflow,
+                )
+                .await?;
+                second.commit().await?;
+                Ok::<_, crate::errors::Error>(())
+            }
+        });
+        sleep(Duration::from_millis(100)).await;
+        todos::update_todo(&mut first, &first_id, &rename("first, renamed"), &workflow)
+            .await
+            .unwrap();
+        first.commit().await.unwrap();
+        second.await.unwrap().unwrap();
+
+        let contents: Vec<String> =
+            sqlx::query_scalar("SELECT content FROM todos ORDER BY position")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(contents, ["first, renamed", "second, renamed"]);
+
+        close_db_pool(pool).await.unwrap();
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+}
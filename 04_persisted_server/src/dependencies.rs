@@ -0,0 +1,127 @@
+use sqlx::SqliteConnection;
+use uuid::Uuid;
+
+use crate::{
+    errors::{Error, Result},
+    todos::{self, Todo},
+};
+
+/// Declares that `todo_id` is blocked by `blocker_id`
+pub async fn add_dependency(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+    blocker_id: &Uuid,
+) -> Result<()> {
+    if !todos::is_live(&mut *conn, todo_id).await? {
+        return Err(Error::NotFound);
+    }
+    if !todos::is_live(&mut *conn, blocker_id).await? {
+        return Err(Error::Invalid(format!(
+            "the blocker {blocker_id} doesn't exist"
+        )));
+    }
+
+    // Walks the blockers of the blocker, meeting the todo means there would be a cycle
+    let cycle = sqlx::query("WITH RECURSIVE blockers(id) AS (SELECT ? UNION SELECT todo_dependencies.blocker_id FROM todo_dependencies JOIN blockers ON todo_dependencies.todo_id = blockers.id) SELECT id FROM blockers WHERE id = ?")
+        .bind(blocker_id)
+        .bind(todo_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+    if cycle.is_some() {
+        return Err(Error::Conflict(format!(
+            "{blocker_id} can't block {todo_id}, it would create a cycle"
+        )));
+    }
+
+    sqlx::query("INSERT OR IGNORE INTO todo_dependencies (todo_id, blocker_id) VALUES (?, ?)")
+        .bind(todo_id)
+        .bind(blocker_id)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn remove_dependency(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+    blocker_id: &Uuid,
+) -> Result<()> {
+    let result = sqlx::query("DELETE FROM todo_dependencies WHERE todo_id = ? AND blocker_id = ?")
+        .bind(todo_id)
+        .bind(blocker_id)
+        .execute(conn)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound);
+    }
+
+    Ok(())
+}
+
+/// Lists the todos blocking the todo, completed or not
+pub async fn select_blockers(conn: &mut SqliteConnection, todo_id: &Uuid) -> Result<Vec<Todo>> {
+    if !todos::is_live(&mut *conn, todo_id).await? {
+        return Err(Error::NotFound);
+    }
+
+    todos::select_todos_where(
+        conn,
+        "id IN (SELECT blocker_id FROM todo_dependencies WHERE todo_id = ?)",
+        todo_id,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        db::create_test_db_pool, errors::Error, payloads::NewTodoRequest,
+        workflow::DEFAULT_WORKFLOW,
+    };
+
+    async fn create_todos(conn: &mut SqliteConnection, count: usize) -> Vec<Uuid> {
+        let workflow = DEFAULT_WORKFLOW.parse().unwrap();
+        let mut ids = Vec::new();
+        for index in 0..count {
+            let new_todo = NewTodoRequest {
+                content: format!("todo {index}"),
+                ..Default::default()
+            };
+            ids.push(
+                todos::create_todo(conn, &new_todo, &workflow)
+                    .await
+                    .unwrap(),
+            );
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn rejects_a_todo_blocking_itself() {
+        let pool = create_test_db_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        let ids = create_todos(&mut conn, 1).await;
+
+        let result = add_dependency(&mut conn, &ids[0], &ids[0]).await;
+        assert!(matches!(result, Err(Error::Conflict(_))), "{result:?}");
+    }
+
+    #[tokio::test]
+    async fn rejects_the_dependencies_closing_a_cycle() {
+        let pool = create_test_db_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        let ids = create_todos(&mut conn, 3).await;
+        add_dependency(&mut conn, &ids[0], &ids[1]).await.unwrap();
+        add_dependency(&mut conn, &ids[1], &ids[2]).await.unwrap();
+
+        let result = add_dependency(&mut conn, &ids[2], &ids[0]).await;
+        assert!(matches!(result, Err(Error::Conflict(_))), "{result:?}");
+        // Blocking the todos in the same direction is no cycle
+        add_dependency(&mut conn, &ids[0], &ids[2]).await.unwrap();
+        let blockers = select_blockers(&mut conn, &ids[0]).await.unwrap();
+        assert_eq!(blockers.len(), 2);
+    }
+}
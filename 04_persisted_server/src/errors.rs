@@ -1,17 +1,58 @@
+use std::fmt;
+
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
 
-pub struct Error(anyhow::Error);
+#[derive(Debug)]
+pub enum Error {
+    NotFound,
+    /// The user isn't allowed to perform the operation
+    Forbidden(String),
+    Conflict(String),
+    /// The request is well-formed but can't be applied
+    Invalid(String),
+    TooLarge(String),
+    /// An operation of a batch failed, the whole batch has been rolled back
+    Batch {
+        index: usize,
+        source: Box<Error>,
+    },
+    Internal(anyhow::Error),
+}
+
+impl Error {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Batch { source, .. } => source.status_code(),
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "not found"),
+            Self::Forbidden(reason) => write!(f, "forbidden: {reason}"),
+            Self::Conflict(reason) => write!(f, "conflict: {reason}"),
+            Self::Invalid(reason) => write!(f, "invalid request: {reason}"),
+            Self::TooLarge(reason) => write!(f, "too large: {reason}"),
+            Self::Batch { index, source } => write!(f, "operation {index} failed: {source}"),
+            Self::Internal(err) => write!(f, "an error occured: {err}"),
+        }
+    }
+}
 
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("an error occured: {}", self.0),
-        )
-            .into_response()
+        (self.status_code(), self.to_string()).into_response()
     }
 }
 
@@ -20,7 +61,7 @@ where
     E: Into<anyhow::Error>,
 {
     fn from(err: E) -> Self {
-        Self(err.into())
+        Self::Internal(err.into())
     }
 }
 
@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+use crate::db::MIGRATIONS_VERSION;
+
+/// The database must answer within this delay for the server to be ready
+static READINESS_DB_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Liveness {
+    pub alive: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Readiness {
+    /// Whether all the checks passed
+    pub ready: bool,
+    pub database: Check,
+    pub migrations: Check,
+    pub shutdown: Check,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status")]
+pub enum Check {
+    #[serde(rename = "ok")]
+    Ok,
+    #[serde(rename = "failed")]
+    Failed { reason: String },
+}
+
+impl Check {
+    fn is_ok(&self) -> bool {
+        matches!(self, Check::Ok)
+    }
+}
+
+/// The server is ready when the database is reachable and fully migrated, and the server is not
+/// shutting down
+pub async fn check_readiness(pool: &Pool<Sqlite>, shutdown: &CancellationToken) -> Readiness {
+    let version = timeout(
+        READINESS_DB_TIMEOUT,
+        sqlx::query_scalar::<_, i64>("SELECT version FROM migrations").fetch_one(pool),
+    )
+    .await;
+    let (database, migrations) = match version {
+        Err(_) => (
+            Check::Failed {
+                reason: format!("no answer within {READINESS_DB_TIMEOUT:?}"),
+            },
+            unknown_migrations(),
+        ),
+        Ok(Err(err)) => (
+            Check::Failed {
+                reason: err.to_string(),
+            },
+            unknown_migrations(),
+        ),
+        Ok(Ok(version)) if version != MIGRATIONS_VERSION => (
+            Check::Ok,
+            Check::Failed {
+                reason: format!("at version {version}, expected {MIGRATIONS_VERSION}"),
+            },
+        ),
+        Ok(Ok(_)) => (Check::Ok, Check::Ok),
+    };
+    let shutdown = if shutdown.is_cancelled() {
+        Check::Failed {
+            reason: "shutting down".to_string(),
+        }
+    } else {
+        Check::Ok
+    };
+
+    Readiness {
+        ready: database.is_ok() && migrations.is_ok() && shutdown.is_ok(),
+        database,
+        migrations,
+        shutdown,
+    }
+}
+
+fn unknown_migrations() -> Check {
+    Check::Failed {
+        reason: "unknown version, the database is unreachable".to_string(),
+    }
+}
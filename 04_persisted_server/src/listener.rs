@@ -0,0 +1,171 @@
+use std::{
+    env, fmt,
+    fs::{self, Permissions},
+    io,
+    net::SocketAddr,
+    os::{
+        fd::{FromRawFd, OwnedFd, RawFd},
+        unix::fs::{FileTypeExt, PermissionsExt},
+    },
+    path::PathBuf,
+    process,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use socket2::{Domain, SockRef, Type};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use tracing::warn;
+
+use crate::config::{ListenerKind, ServerConfig};
+
+/// The first file descriptor passed by systemd, see `sd_listen_fds`
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// Accepts the connections from a TCP address, a Unix socket, or a socket passed by systemd
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix {
+        listener: UnixListener,
+        /// The socket file created by the server, removed when dropped
+        path: Option<PathBuf>,
+    },
+}
+
+pub enum Connection {
+    Tcp(TcpStream, SocketAddr),
+    /// The peers of a Unix socket have no address worth keeping
+    Unix(UnixStream),
+}
+
+impl Listener {
+    pub async fn bind(config: &ServerConfig) -> Result<Self> {
+        match config.listener {
+            ListenerKind::Tcp => Ok(Self::Tcp(TcpListener::bind(config.bind).await?)),
+            ListenerKind::Unix => bind_unix(config),
+            ListenerKind::Systemd => from_systemd(),
+        }
+    }
+
+    pub async fn accept(&self) -> io::Result<Connection> {
+        match self {
+            Self::Tcp(listener) => {
+                let (stream, address) = listener.accept().await?;
+                Ok(Connection::Tcp(stream, address))
+            }
+            Self::Unix { listener, .. } => {
+                let (stream, _) = listener.accept().await?;
+                Ok(Connection::Unix(stream))
+            }
+        }
+    }
+
+    /// Where the plain HTTP requests are redirected to, only known for TCP
+    pub fn port(&self) -> Option<u16> {
+        match self {
+            Self::Tcp(listener) => listener.local_addr().ok().map(|address| address.port()),
+            Self::Unix { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(listener) => match listener.local_addr() {
+                Ok(address) => write!(f, "{address}"),
+                Err(_) => write!(f, "a TCP socket"),
+            },
+            Self::Unix { listener, .. } => {
+                match listener.local_addr().ok().and_then(|address| {
+                    address.as_pathname().map(|path| path.display().to_string())
+                }) {
+                    Some(path) => write!(f, "unix:{path}"),
+                    None => write!(f, "an unnamed Unix socket"),
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Self::Unix {
+            path: Some(path), ..
+        } = self
+        {
+            if let Err(err) = fs::remove_file(&*path) {
+                warn!("couldn't remove the socket {}: {err}", path.display());
+            }
+        }
+    }
+}
+
+/// Replaces the socket left by a previous run, but no other kind of file
+fn bind_unix(config: &ServerConfig) -> Result<Listener> {
+    let path = &config.unix_socket_path;
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)
+            .with_context(|| format!("can't remove the stale socket {}", path.display()))?,
+        Ok(_) => bail!("{} exists and isn't a socket", path.display()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => bail!("can't access {}: {err}", path.display()),
+    }
+
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("can't bind the socket {}", path.display()))?;
+    // Removes the socket if setting its permissions fails
+    let listener = Listener::Unix {
+        listener,
+        path: Some(path.clone()),
+    };
+    let mode = config
+        .unix_socket_permissions()
+        .ok_or_else(|| anyhow!("invalid octal permissions {}", config.unix_socket_mode))?;
+    fs::set_permissions(path, Permissions::from_mode(mode))
+        .with_context(|| format!("can't set the permissions of {}", path.display()))?;
+
+    Ok(listener)
+}
+
+/// Takes the socket passed by systemd socket activation, following the `sd_listen_fds` protocol
+fn from_systemd() -> Result<Listener> {
+    let listen_pid = env::var("LISTEN_PID");
+    let listen_fds = env::var("LISTEN_FDS");
+    // Not to be passed on to the child processes
+    for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        env::remove_var(name);
+    }
+
+    let listen_pid = listen_pid
+        .context("LISTEN_PID isn't set, is the server started by a systemd socket unit?")?;
+    if listen_pid.parse::<u32>().ok() != Some(process::id()) {
+        bail!("LISTEN_PID {listen_pid} isn't the process of the server");
+    }
+    let listen_fds = listen_fds.context("LISTEN_FDS isn't set")?;
+    match listen_fds.parse::<u32>() {
+        Ok(1) => {}
+        Ok(count) => bail!("expected a single socket from systemd, got {count}"),
+        Err(_) => bail!("invalid LISTEN_FDS {listen_fds}"),
+    }
+
+    // SAFETY: per the protocol, the descriptor is open and owned by nothing else in the process
+    let fd = unsafe { OwnedFd::from_raw_fd(SD_LISTEN_FDS_START) };
+    let socket = SockRef::from(&fd);
+    // Accepting from any other socket would fail over and over instead of refusing to start
+    if socket.r#type()? != Type::STREAM || !socket.is_listener()? {
+        bail!("the socket passed by systemd isn't a listening stream socket");
+    }
+    socket.set_cloexec(true)?;
+    socket.set_nonblocking(true)?;
+
+    match socket.domain()? {
+        Domain::IPV4 | Domain::IPV6 => Ok(Listener::Tcp(TcpListener::from_std(fd.into())?)),
+        // The socket belongs to systemd, it's not removed
+        Domain::UNIX => Ok(Listener::Unix {
+            listener: UnixListener::from_std(fd.into())?,
+            path: None,
+        }),
+        _ => bail!("the socket passed by systemd is neither TCP nor Unix"),
+    }
+}
@@ -0,0 +1,367 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, SqliteConnection};
+
+use crate::{
+    audit::Auditor,
+    config::LoginConfig,
+    errors::{Error, Result},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type)]
+pub enum SubjectKind {
+    #[serde(rename = "username")]
+    #[sqlx(rename = "username")]
+    Username,
+    #[serde(rename = "ip")]
+    #[sqlx(rename = "ip")]
+    Ip,
+}
+
+/// What the failed attempts are tracked for, both the username and the address of the client
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Subject {
+    pub kind: SubjectKind,
+    #[serde(rename = "subject")]
+    pub value: String,
+}
+
+impl Subject {
+    pub fn username(username: &str) -> Self {
+        Self {
+            kind: SubjectKind::Username,
+            value: username.to_string(),
+        }
+    }
+
+    pub fn ip(ip: IpAddr) -> Self {
+        Self {
+            kind: SubjectKind::Ip,
+            value: ip.to_string(),
+        }
+    }
+
+    /// The addresses are compared in their canonical form
+    pub fn parse(kind: SubjectKind, value: &str) -> Result<Self> {
+        match kind {
+            SubjectKind::Username => Ok(Self::username(value)),
+            SubjectKind::Ip => value
+                .parse()
+                .map(Self::ip)
+                .map_err(|_| Error::Invalid(format!("invalid address {value}"))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Failures {
+    /// Consecutive failed attempts
+    count: u32,
+    last_failed_at: DateTime<Utc>,
+    /// No attempt is allowed until then
+    blocked_until: DateTime<Utc>,
+}
+
+/// A subject locked out after too many failed attempts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockout {
+    #[serde(flatten)]
+    pub subject: Subject,
+    pub failures: u32,
+    pub locked_until: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+pub enum LockoutAction {
+    /// Automatically, after too many failed attempts
+    #[serde(rename = "lock")]
+    #[sqlx(rename = "lock")]
+    Lock,
+    /// By an admin
+    #[serde(rename = "clear")]
+    #[sqlx(rename = "clear")]
+    Clear,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct LockoutEntry {
+    pub id: i64,
+    pub action: LockoutAction,
+    pub kind: SubjectKind,
+    pub subject: String,
+    pub failures: Option<i64>,
+    pub locked_until: Option<DateTime<Utc>>,
+    /// The admin who cleared the lockout, `SIGUSR1` when cleared by the signal
+    pub actor: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub request_id: Option<String>,
+}
+
+/// The failed authentication attempts, kept in memory, cloning it shares the same attempts.
+/// Each failure delays the next attempt exponentially, until the subject gets locked out.
+/// The lockouts are lifted by an admin, or by sending `SIGUSR1` to the server when the admin
+/// is the one locked out.
+#[derive(Debug, Clone)]
+pub struct LoginGuard {
+    config: Arc<LoginConfig>,
+    failures: Arc<Mutex<HashMap<Subject, Failures>>>,
+}
+
+impl LoginGuard {
+    pub fn new(config: LoginConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            failures: Arc::default(),
+        }
+    }
+
+    /// What the attempt of the user from the address is tracked for
+    pub fn subjects(&self, username: &str, address: Option<IpAddr>) -> Vec<Subject> {
+        let mut subjects = vec![Subject::username(username)];
+        if let Some(address) = address.filter(|_| self.config.track_addresses) {
+            subjects.push(Subject::ip(address));
+        }
+
+        subjects
+    }
+
+    /// Until when the attempts of any of the subjects are refused, if they are
+    pub fn blocked_until(&self, subjects: &[Subject], now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let failures = self.failures.lock().unwrap();
+        subjects
+            .iter()
+            .filter_map(|subject| failures.get(subject))
+            .map(|failures| failures.blocked_until)
+            .filter(|blocked_until| *blocked_until > now)
+            .max()
+    }
+
+    /// Returns the subjects this failure locked out
+    pub fn record_failure(&self, subjects: &[Subject], now: DateTime<Utc>) -> Vec<Lockout> {
+        let mut failures = self.failures.lock().unwrap();
+        let mut lockouts = Vec::new();
+        for subject in subjects {
+            if !failures.contains_key(subject) && failures.len() >= self.config.max_subjects {
+                forget_least_recent(&mut failures, now);
+            }
+            let failures = failures.entry(subject.clone()).or_insert(Failures {
+                count: 0,
+                last_failed_at: now,
+                blocked_until: now,
+            });
+            failures.count = failures.count.saturating_add(1);
+            failures.last_failed_at = now;
+            if failures.count >= self.config.lockout_threshold {
+                failures.blocked_until = now + secs(self.config.lockout_secs);
+                lockouts.push(Lockout {
+                    subject: subject.clone(),
+                    failures: failures.count,
+                    locked_until: failures.blocked_until,
+                });
+            } else {
+                failures.blocked_until = now + self.backoff(failures.count);
+            }
+        }
+
+        lockouts
+    }
+
+    /// Only the failures of the username are forgotten, an address can be shared by several users
+    pub fn record_success(&self, username: &str) {
+        self.failures
+            .lock()
+            .unwrap()
+            .remove(&Subject::username(username));
+    }
+
+    /// Forgets the failures of the subject, returning whether there were some
+    pub fn clear(&self, subject: &Subject) -> bool {
+        self.failures.lock().unwrap().remove(subject).is_some()
+    }
+
+    /// Forgets all the failures, returning the lifted lockouts
+    pub fn clear_all(&self, now: DateTime<Utc>) -> Vec<Lockout> {
+        let lockouts = self.lockouts(now);
+        self.failures.lock().unwrap().clear();
+
+        lockouts
+    }
+
+    pub fn lockouts(&self, now: DateTime<Utc>) -> Vec<Lockout> {
+        let mut lockouts: Vec<Lockout> = self
+            .failures
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, failures)| {
+                failures.count >= self.config.lockout_threshold && failures.blocked_until > now
+            })
+            .map(|(subject, failures)| Lockout {
+                subject: subject.clone(),
+                failures: failures.count,
+                locked_until: failures.blocked_until,
+            })
+            .collect();
+        lockouts.sort_by_key(|lockout| lockout.locked_until);
+
+        lockouts
+    }
+
+    /// Forgets the subjects that are not blocked and didn't fail for `failures_ttl_secs`, returning
+    /// how many were forgotten
+    pub fn evict(&self, now: DateTime<Utc>) -> usize {
+        let forget_before = now - secs(self.config.failures_ttl_secs);
+        let mut failures = self.failures.lock().unwrap();
+        let len = failures.len();
+        failures.retain(|_, failures| {
+            failures.blocked_until > now || failures.last_failed_at > forget_before
+        });
+
+        len - failures.len()
+    }
+
+    /// `backoff_secs` after the first failure, doubled after each following one
+    fn backoff(&self, count: u32) -> Duration {
+        let backoff_secs = self
+            .config
+            .backoff_secs
+            .saturating_mul(2_u64.saturating_pow(count.saturating_sub(1)))
+            .min(self.config.max_backoff_secs);
+
+        secs(backoff_secs)
+    }
+}
+
+/// Makes room for a new subject, the locked out subjects being forgotten last
+fn forget_least_recent(failures: &mut HashMap<Subject, Failures>, now: DateTime<Utc>) {
+    let least_recent = failures
+        .iter()
+        .min_by_key(|(_, failures)| (failures.blocked_until > now, failures.last_failed_at))
+        .map(|(subject, _)| subject.clone());
+    if let Some(subject) = least_recent {
+        failures.remove(&subject);
+    }
+}
+
+/// The durations of the configuration are validated not to exceed a year
+fn secs(secs: u64) -> Duration {
+    Duration::seconds(i64::try_from(secs).unwrap_or(i64::MAX / 1000))
+}
+
+pub async fn record_lockout(
+    conn: &mut SqliteConnection,
+    lockout: &Lockout,
+    request_id: Option<&str>,
+) -> Result<()> {
+    sqlx::query("INSERT INTO lockout_log (action, kind, subject, failures, locked_until, created_at, request_id) VALUES (?, ?, ?, ?, ?, ?, ?)")
+        .bind(LockoutAction::Lock)
+        .bind(lockout.subject.kind)
+        .bind(&lockout.subject.value)
+        .bind(lockout.failures)
+        .bind(lockout.locked_until)
+        .bind(Utc::now())
+        .bind(request_id)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn record_clearing(
+    conn: &mut SqliteConnection,
+    auditor: &Auditor,
+    subject: &Subject,
+) -> Result<()> {
+    sqlx::query("INSERT INTO lockout_log (action, kind, subject, actor, created_at, request_id) VALUES (?, ?, ?, ?, ?, ?)")
+        .bind(LockoutAction::Clear)
+        .bind(subject.kind)
+        .bind(&subject.value)
+        .bind(&auditor.actor)
+        .bind(Utc::now())
+        .bind(&auditor.request_id)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn select_lockout_entries(conn: &mut SqliteConnection) -> Result<Vec<LockoutEntry>> {
+    let entries = sqlx::query_as("SELECT id, action, kind, subject, failures, locked_until, actor, created_at, request_id FROM lockout_log ORDER BY id")
+        .fetch_all(conn)
+        .await?;
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Locks out after two failures
+    fn guard(config: &LoginConfig) -> LoginGuard {
+        LoginGuard::new(LoginConfig {
+            lockout_threshold: 2,
+            ..config.clone()
+        })
+    }
+
+    #[test]
+    fn only_tracks_the_addresses_when_enabled() {
+        let address = IpAddr::from([127, 0, 0, 1]);
+        let tracking = guard(&LoginConfig::default());
+        assert_eq!(
+            tracking.subjects("alice", Some(address)),
+            [Subject::username("alice"), Subject::ip(address)]
+        );
+
+        let not_tracking = guard(&LoginConfig {
+            track_addresses: false,
+            ..LoginConfig::default()
+        });
+        assert_eq!(
+            not_tracking.subjects("alice", Some(address)),
+            [Subject::username("alice")]
+        );
+    }
+
+    #[test]
+    fn forgets_the_least_recent_failures_beyond_the_maximum() {
+        let guard = guard(&LoginConfig {
+            max_subjects: 2,
+            ..LoginConfig::default()
+        });
+        let now = Utc::now();
+        let alice = [Subject::username("alice")];
+        guard.record_failure(&alice, now);
+        guard.record_failure(&alice, now);
+        guard.record_failure(&[Subject::username("bob")], now);
+
+        guard.record_failure(&[Subject::username("carol")], now + Duration::seconds(1));
+
+        let failures = guard.failures.lock().unwrap();
+        assert_eq!(failures.len(), 2);
+        // Locked out, so kept over the failures of bob
+        assert!(failures.contains_key(&alice[0]));
+        assert!(failures.contains_key(&Subject::username("carol")));
+    }
+
+    #[test]
+    fn clears_all_the_lockouts() {
+        let guard = guard(&LoginConfig::default());
+        let now = Utc::now();
+        let subjects = guard.subjects("admin", Some(IpAddr::from([127, 0, 0, 1])));
+        guard.record_failure(&subjects, now);
+        let lockouts = guard.record_failure(&subjects, now);
+        assert_eq!(lockouts.len(), 2);
+
+        assert_eq!(guard.clear_all(now).len(), 2);
+        assert!(guard.lockouts(now).is_empty());
+        assert_eq!(guard.blocked_until(&subjects, now), None);
+    }
+}
@@ -1,118 +1,1288 @@
 #![deny(clippy::all)]
 #![deny(clippy::pedantic)]
 
-use std::time::Duration;
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use attachments::AttachmentStorage;
 use axum::{
-    extract::{Path, Query, State},
-    http::{Method, StatusCode},
+    body::Body,
+    extract::{DefaultBodyLimit, Multipart, Path, Query, State},
+    http::{
+        header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE},
+        HeaderName, Method, StatusCode,
+    },
     middleware,
-    routing::{delete, get, post, put},
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post, put},
     Json, Router,
 };
-use db::create_db_pool;
-use middlewares::auth;
-use payloads::{NewTodoRequest, TodoCompletedRequest};
-use sqlx::{Pool, Sqlite};
-use tokio::net::TcpListener;
+use chrono::Utc;
+use clap::Parser;
+use config::{Cli, Config, ServerConfig};
+use db::{close_db_pool, conform_todo_states, create_db_pool};
+use middlewares::{admin, auth, client_address, echo_request_id, record_response, request_span};
+use notifications::{NotificationSink, Notifier};
+use payloads::{
+    BatchOperation, BatchOperationResult, BatchRequest, BatchResponse, ClearLockoutRequest,
+    CommentRequest, CreatedResponse, DependencyRequest, MoveTodoRequest, NewTodoRequest,
+    RemoveTodoRequest, RemoveTodosRequest, RevertTodoRequest, TagRequest, TimeEntryRequest,
+    TodoCompletedRequest, TodosResponse, TransitionRequest, UpdateTodoRequest,
+};
+use sqlx::{pool::PoolConnection, Pool, Sqlite, SqliteConnection, Transaction};
+use tokio::{
+    fs::File,
+    signal::unix::{signal, SignalKind},
+};
+use tokio_util::{io::ReaderStream, sync::CancellationToken};
 use tower::ServiceBuilder;
 use tower_http::{
-    cors::{Any, CorsLayer},
+    cors::{AllowOrigin, Any, CorsLayer},
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
     timeout::TimeoutLayer,
+    trace::TraceLayer,
 };
+use tracing::{error, info};
 use uuid::Uuid;
 
-use crate::errors::Result;
-use crate::payloads::TodosFilterRequest;
-use crate::todos::Todo;
+use crate::attachments::Attachment;
+use crate::audit::{AuditAction, AuditEntry, AuditFilter, Auditor};
+use crate::comments::Comment;
+use crate::errors::{Error, Result};
+use crate::health::{Liveness, Readiness};
+use crate::lockouts::{Lockout, LockoutEntry, LoginGuard, Subject};
+use crate::metrics::{Metrics, MetricsLayer};
+use crate::payloads::{
+    NewProjectRequest, ProjectsFilterRequest, TodosFilterRequest, UpdateProjectRequest,
+};
+use crate::projects::Project;
+use crate::rate_limit::{rate_limit, rate_limit_failures, RateLimiter};
+use crate::revisions::TodoRevision;
+use crate::tags::Tag;
+use crate::telemetry::init_telemetry;
+use crate::time_tracking::{TimeReport, TimeReportFilter, TodoTime};
+use crate::todos::{ChildrenRemoval, Todo, TodoRecord, TrashedTodo};
+use crate::workflow::Workflow;
 
+mod attachments;
+mod audit;
+mod comments;
+mod config;
 mod db;
+mod dependencies;
 mod errors;
+mod health;
+mod listener;
+mod lockouts;
+mod metrics;
 mod middlewares;
+mod notifications;
+#[cfg(feature = "otel")]
+mod otel;
 mod payloads;
+mod positions;
+mod projects;
+mod rate_limit;
+mod recurrence;
+mod revisions;
+mod server;
+mod tags;
+mod tasks;
+mod telemetry;
+mod time_tracking;
+mod tls;
 mod todos;
-
-static DB_FILENAME: &str = "todos.db";
+mod workflow;
 
 #[derive(Clone)]
 struct AppState {
     pool: Pool<Sqlite>,
+    workflow: Arc<Workflow>,
+    attachments: Arc<AttachmentStorage>,
+    /// Cancelled once the server starts shutting down
+    shutdown: CancellationToken,
+    metrics: Metrics,
+    login_guard: LoginGuard,
+}
+
+impl AppState {
+    /// Acquires a connection from the pool, timing the wait
+    async fn acquire(&self) -> sqlx::Result<PoolConnection<Sqlite>> {
+        let started_at = Instant::now();
+        let conn = self.pool.acquire().await;
+        self.metrics.observe_acquire(started_at);
+        conn
+    }
+
+    /// Begins a write transaction on a connection from the pool, timing the wait
+    async fn begin(&self) -> sqlx::Result<Transaction<'static, Sqlite>> {
+        let started_at = Instant::now();
+        let tx = db::begin_write(&self.pool).await;
+        self.metrics.observe_acquire(started_at);
+        tx
+    }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
+    // Configuration
+    let cli = Cli::parse();
+    let config = Config::load(&cli)?;
+    if cli.print_config {
+        print!("{}", toml::to_string_pretty(&config)?);
+        return Ok(());
+    }
+    let _telemetry = init_telemetry(&config)?;
+
+    // Shutdown
+    let shutdown = CancellationToken::new();
+    cancel_on_signal(shutdown.clone())?;
 
     // State
-    let pool = create_db_pool(DB_FILENAME).await?;
-    let state = AppState { pool };
+    let pool = create_db_pool(&config.database).await?;
+    let workflow: Workflow = config.workflow.parse().map_err(anyhow::Error::msg)?;
+    conform_todo_states(&pool, &workflow).await?;
+    let attachments =
+        AttachmentStorage::new(config.attachments.dir.clone(), config.attachments.max_size);
+    tokio::fs::create_dir_all(&attachments.dir).await?;
+    let state = AppState {
+        pool: pool.clone(),
+        workflow: Arc::new(workflow),
+        attachments: Arc::new(attachments.clone()),
+        shutdown: shutdown.clone(),
+        metrics: Metrics::new()?,
+        login_guard: LoginGuard::new(config.login.clone()),
+    };
+    clear_lockouts_on_signal(state.clone())?;
 
+    // Background tasks
+    let trash_purge = tasks::spawn_trash_purge(
+        pool.clone(),
+        Duration::from_secs(config.trash.retention_days * 24 * 60 * 60),
+        attachments,
+        shutdown.clone(),
+    );
+
+    let reminder_sinks = config
+        .reminders
+        .webhooks
+        .iter()
+        .map(|url| NotificationSink::Webhook(url.clone()))
+        .collect();
+    let reminder_scheduler = tasks::spawn_reminder_scheduler(
+        pool.clone(),
+        Notifier::new(reminder_sinks),
+        shutdown.clone(),
+    );
+
+    let rate_limiter = RateLimiter::new(config.rate_limit.clone());
+    let rate_limit_eviction =
+        tasks::spawn_rate_limit_eviction(rate_limiter.clone(), shutdown.clone());
+    let login_failures_eviction =
+        tasks::spawn_login_failures_eviction(state.login_guard.clone(), shutdown.clone());
+
+    let app = app(state, &config, rate_limiter)?;
+
+    // Starting
+    server::serve(app, &config.server, &config.tls, &shutdown).await?;
+
+    // Stopping, the background tasks complete their current run
+    tasks::join([
+        ("trash purge", trash_purge),
+        ("reminder scheduler", reminder_scheduler),
+        ("rate limit eviction", rate_limit_eviction),
+        ("login failures eviction", login_failures_eviction),
+    ])
+    .await;
+    close_db_pool(pool).await?;
+    info!("shut down");
+
+    Ok(())
+}
+
+/// Layers the middlewares around the routes
+fn app(state: AppState, config: &Config, rate_limiter: RateLimiter) -> anyhow::Result<Router> {
     // Middlewares
-    let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
-        .allow_origin(Any);
+    let cors = cors_layer(&config.server)?;
+
+    let timeout = TimeoutLayer::new(Duration::from_secs(config.server.request_timeout_secs));
+    // The attachments are streamed, they take longer than the other requests
+    let attachment_timeout =
+        TimeoutLayer::new(Duration::from_secs(config.attachments.timeout_secs));
+
+    let auth = middleware::from_fn_with_state(state.clone(), auth);
+
+    let request_metrics = MetricsLayer::new(state.metrics.clone());
+
+    let client_address_header = config
+        .server
+        .client_address_header
+        .as_deref()
+        .map(HeaderName::from_str)
+        .transpose()?;
+    let client_address = middleware::from_fn_with_state(client_address_header, client_address);
 
-    let timeout = TimeoutLayer::new(Duration::from_secs(3));
+    let rate_limit_failures =
+        middleware::from_fn_with_state(rate_limiter.clone(), rate_limit_failures);
+    let rate_limit = middleware::from_fn_with_state(rate_limiter, rate_limit);
 
-    let auth = middleware::from_fn(auth);
+    // The request id is generated, unless provided, before the request span is created
+    let request_id = ServiceBuilder::new()
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .layer(PropagateRequestIdLayer::x_request_id());
+
+    let trace = TraceLayer::new_for_http()
+        .make_span_with(request_span)
+        .on_response(record_response);
 
     // Router
-    let exposed_router = Router::new().route("/", get(root));
+    // Not authenticated, for the orchestrator to probe the server, the probes and the metrics
+    // aren't rate limited not to be refused to the orchestrator and the monitoring
+    let exposed_router = Router::new()
+        .route("/", get(root))
+        .route_layer(rate_limit.clone())
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics))
+        .with_state(state.clone());
 
-    let protected_router = Router::new()
-        .route("/todos", get(todos))
-        .route("/todos/new", post(create_todo))
-        .route("/todos/:id/set-completion", put(set_todo_completion))
-        .route("/todos/:id/remove", delete(remove_todo))
+    let admin_router = Router::new()
+        .route("/audit", get(audit_entries))
+        .route("/lockouts", get(lockouts))
+        .route("/lockouts/clear", post(clear_lockout))
+        .route("/lockouts/log", get(lockout_entries))
+        .route_layer(middleware::from_fn(admin));
+
+    let protected_router = protected_routes()
+        .merge(admin_router)
+        .layer(timeout)
+        .merge(attachment_routes().layer(attachment_timeout))
         .with_state(state)
-        .layer(ServiceBuilder::new().layer(cors).layer(timeout).layer(auth));
+        .layer(
+            ServiceBuilder::new()
+                .layer(request_metrics)
+                .layer(request_id)
+                .layer(trace)
+                .layer(middleware::from_fn(echo_request_id))
+                .layer(cors)
+                // By client address, so that failing to authenticate takes from a budget too
+                .layer(rate_limit_failures)
+                .layer(auth)
+                // By user
+                .layer(rate_limit),
+        );
+
+    Ok(Router::new()
+        .merge(exposed_router)
+        .merge(protected_router)
+        .layer(client_address))
+}
 
-    let app = Router::new().merge(exposed_router).merge(protected_router);
+/// Cancels the token on SIGINT or SIGTERM, the server then stops being ready and drains the
+/// in-flight requests
+fn cancel_on_signal(shutdown: CancellationToken) -> std::io::Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+        info!("shutting down");
+        shutdown.cancel();
+    });
 
-    // Starting
-    let listener = TcpListener::bind("0.0.0.0:8000").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    Ok(())
+}
+
+/// Lifts all the lockouts on SIGUSR1, when the admin is locked out and can't lift them
+fn clear_lockouts_on_signal(state: AppState) -> std::io::Result<()> {
+    let mut clear = signal(SignalKind::user_defined1())?;
+    let auditor = Auditor {
+        actor: "SIGUSR1".to_string(),
+        request_id: None,
+    };
+    tokio::spawn(async move {
+        while clear.recv().await.is_some() {
+            let lockouts = state.login_guard.clear_all(Utc::now());
+            info!(
+                "forgot the failed authentications, lifting {} lockouts",
+                lockouts.len()
+            );
+            let recorded = async {
+                let mut conn = state.acquire().await?;
+                for lockout in &lockouts {
+                    lockouts::record_clearing(&mut conn, &auditor, &lockout.subject).await?;
+                }
+                Ok::<_, Error>(())
+            };
+            if let Err(err) = recorded.await {
+                error!("couldn't record the lifted lockouts: {err}");
+            }
+        }
+    });
 
     Ok(())
 }
 
+fn cors_layer(config: &ServerConfig) -> anyhow::Result<CorsLayer> {
+    let allowed_origins = if config.cors_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::list(
+            config
+                .cors_origins
+                .iter()
+                .map(|origin| origin.parse())
+                .collect::<Result<Vec<_>, _>>()?,
+        )
+    };
+
+    Ok(CorsLayer::new()
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_origin(allowed_origins))
+}
+
+/// The routes uploading and downloading the attachments, given their own timeout
+fn attachment_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/todos/:id/attachments",
+            get(todo_attachments)
+                .post(upload_attachment)
+                // The size of the attachments is enforced while streaming them
+                .layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/todos/:id/attachments/:attachment_id",
+            get(download_attachment).delete(remove_attachment),
+        )
+}
+
+/// The routes requiring an authenticated user
+fn protected_routes() -> Router<AppState> {
+    Router::new()
+        .route("/todos", get(todos).delete(remove_todos))
+        .route("/todos/new", post(create_todo))
+        .route("/todos/batch", post(batch_todos))
+        .route("/todos/trash", get(trashed_todos).delete(empty_trash))
+        .route("/todos/:id", patch(update_todo))
+        .route("/todos/:id/set-completion", put(set_todo_completion))
+        .route("/todos/:id/transition", post(transition_todo))
+        .route("/todos/:id/move", post(move_todo))
+        .route("/todos/:id/remove", delete(remove_todo))
+        .route("/todos/:id/restore", post(restore_todo))
+        .route("/todos/:id/purge", delete(purge_todo))
+        .route("/todos/:id/history", get(todo_history))
+        .route("/todos/:id/revert", post(revert_todo))
+        .route("/todos/:id/children", get(todo_children))
+        .route(
+            "/todos/:id/blockers",
+            get(todo_blockers).post(add_todo_blocker),
+        )
+        .route(
+            "/todos/:id/blockers/:blocker_id",
+            delete(remove_todo_blocker),
+        )
+        .route(
+            "/todos/:id/comments",
+            get(todo_comments).post(create_comment),
+        )
+        .route(
+            "/todos/:id/comments/:comment_id",
+            patch(update_comment).delete(remove_comment),
+        )
+        .route("/todos/:id/time", get(todo_time).post(create_time_entry))
+        .route("/todos/:id/time/start", post(start_timer))
+        .route("/todos/:id/time/stop", post(stop_timer))
+        .route("/time/report", get(time_report))
+        .route("/todos/:id/tags", post(add_todo_tag))
+        .route("/todos/:id/tags/:tag", delete(remove_todo_tag))
+        .route("/workflow", get(todos_workflow))
+        .route("/projects", get(all_projects))
+        .route("/projects/new", post(create_project))
+        .route("/projects/:id", get(project).patch(update_project))
+        .route("/projects/:id/todos", get(project_todos))
+        .route("/projects/:id/remove", delete(remove_project))
+        .route("/tags", get(all_tags))
+        .route("/tags/new", post(create_tag))
+        .route("/tags/:tag/rename", put(rename_tag))
+        .route("/tags/:tag/remove", delete(remove_tag))
+}
+
 async fn root() -> &'static str {
     "Hello Todos!"
 }
 
+async fn healthz() -> Json<Liveness> {
+    Json(Liveness { alive: true })
+}
+
+async fn metrics(State(state): State<AppState>) -> Result<String> {
+    state.metrics.render(&state.pool).await
+}
+
+async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<Readiness>) {
+    let readiness = health::check_readiness(&state.pool, &state.shutdown).await;
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
 async fn todos(
     State(state): State<AppState>,
     todos_filters: Option<Query<TodosFilterRequest>>,
-) -> Result<Json<Vec<Todo>>> {
-    let conn = state.pool.acquire().await?;
-    let todos = todos::select_todos(conn, todos_filters.unwrap_or_default().filter).await?;
-    Ok(Json(todos))
+) -> Result<Json<TodosResponse>> {
+    let todos_filters = todos_filters.unwrap_or_default();
+    let mut conn = state.acquire().await?;
+    let todos = todos::select_todos(&mut conn, &todos_filters).await?;
+    if todos_filters.tree {
+        return Ok(Json(TodosResponse::Tree(todos::build_tree(todos))));
+    }
+    Ok(Json(TodosResponse::List(todos)))
 }
 
 async fn create_todo(
     State(state): State<AppState>,
+    auditor: Auditor,
     Json(new_todo): Json<NewTodoRequest>,
 ) -> Result<StatusCode> {
-    let conn = state.pool.acquire().await?;
-    todos::create_todo(conn, &new_todo.content).await?;
+    let mut tx = state.begin().await?;
+    let todo_id = todos::create_todo(&mut tx, &new_todo, &state.workflow).await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Create, &todo_id, None).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn update_todo(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+    Json(changes): Json<UpdateTodoRequest>,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+    let next_occurrence = todos::update_todo(&mut tx, &todo_id, &changes, &state.workflow).await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Update, &todo_id, before).await?;
+    record_next_occurrence(&mut tx, &auditor, next_occurrence).await?;
+    tx.commit().await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
 async fn set_todo_completion(
     Path(todo_id): Path<Uuid>,
     State(state): State<AppState>,
+    auditor: Auditor,
     Json(todo_completed): Json<TodoCompletedRequest>,
 ) -> Result<StatusCode> {
-    let conn = state.pool.acquire().await?;
-    todos::set_todo_completion(conn, &todo_id, todo_completed.completed).await?;
+    let mut tx = state.begin().await?;
+    let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+    let next_occurrence = todos::set_todo_completion(
+        &mut tx,
+        &todo_id,
+        todo_completed.completed,
+        todo_completed.force,
+        &state.workflow,
+    )
+    .await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Update, &todo_id, before).await?;
+    record_next_occurrence(&mut tx, &auditor, next_occurrence).await?;
+    if todo_completed.completed && todo_completed.cascade {
+        for descendant_id in todos::select_descendant_ids(&mut tx, &todo_id).await? {
+            let before = todos::select_todo_record(&mut tx, &descendant_id).await?;
+            let next_occurrence = todos::set_todo_completion(
+                &mut tx,
+                &descendant_id,
+                true,
+                todo_completed.force,
+                &state.workflow,
+            )
+            .await?;
+            audit::record_change(
+                &mut tx,
+                &auditor,
+                AuditAction::Update,
+                &descendant_id,
+                before,
+            )
+            .await?;
+            record_next_occurrence(&mut tx, &auditor, next_occurrence).await?;
+        }
+    }
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn transition_todo(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+    Json(transition): Json<TransitionRequest>,
+) -> Result<StatusCode> {
+    let changes = UpdateTodoRequest {
+        state: Some(transition.state),
+        force: transition.force,
+        ..Default::default()
+    };
+
+    let mut tx = state.begin().await?;
+    let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+    let next_occurrence = todos::update_todo(&mut tx, &todo_id, &changes, &state.workflow).await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Update, &todo_id, before).await?;
+    record_next_occurrence(&mut tx, &auditor, next_occurrence).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Audits the creation of the next occurrence of a recurring todo that has been completed
+async fn record_next_occurrence(
+    conn: &mut SqliteConnection,
+    auditor: &Auditor,
+    next_occurrence: Option<Uuid>,
+) -> Result<()> {
+    if let Some(next_id) = next_occurrence {
+        audit::record_change(conn, auditor, AuditAction::Create, &next_id, None).await?;
+    }
+
+    Ok(())
+}
+
+async fn move_todo(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+    Json(todo_move): Json<MoveTodoRequest>,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+    todos::move_todo(&mut tx, &todo_id, &todo_move).await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Update, &todo_id, before).await?;
+    tx.commit().await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
 async fn remove_todo(
     Path(todo_id): Path<Uuid>,
     State(state): State<AppState>,
+    auditor: Auditor,
+    Query(removal): Query<RemoveTodoRequest>,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    remove_todo_with_children(
+        &mut tx,
+        &auditor,
+        &state.workflow,
+        &todo_id,
+        removal.children,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn remove_todo_with_children(
+    conn: &mut SqliteConnection,
+    auditor: &Auditor,
+    workflow: &Workflow,
+    todo_id: &Uuid,
+    children: ChildrenRemoval,
+) -> Result<()> {
+    match children {
+        ChildrenRemoval::Orphan => {
+            let changes = UpdateTodoRequest {
+                parent_id: Some(None),
+                ..Default::default()
+            };
+            for child in todos::select_children(&mut *conn, todo_id).await? {
+                let before = todos::select_todo_record(&mut *conn, &child.id).await?;
+                todos::update_todo(&mut *conn, &child.id, &changes, workflow).await?;
+                audit::record_change(&mut *conn, auditor, AuditAction::Update, &child.id, before)
+                    .await?;
+            }
+        }
+        ChildrenRemoval::Cascade => {
+            for descendant_id in todos::select_descendant_ids(&mut *conn, todo_id).await? {
+                let before = todos::select_todo_record(&mut *conn, &descendant_id).await?;
+                todos::remove_todo(&mut *conn, &descendant_id).await?;
+                audit::record_change(
+                    &mut *conn,
+                    auditor,
+                    AuditAction::Remove,
+                    &descendant_id,
+                    before,
+                )
+                .await?;
+            }
+        }
+    }
+
+    let before = todos::select_todo_record(&mut *conn, todo_id).await?;
+    todos::remove_todo(&mut *conn, todo_id).await?;
+    audit::record_change(conn, auditor, AuditAction::Remove, todo_id, before).await
+}
+
+// The filter is mandatory here, a bare `DELETE /todos` must not wipe everything
+async fn remove_todos(
+    State(state): State<AppState>,
+    auditor: Auditor,
+    Query(remove_todos): Query<RemoveTodosRequest>,
+) -> Result<StatusCode> {
+    let filters = TodosFilterRequest {
+        filter: remove_todos.filter,
+        ..Default::default()
+    };
+
+    let mut tx = state.begin().await?;
+    for todo in todos::select_todos(&mut tx, &filters).await? {
+        remove_todo_with_children(
+            &mut tx,
+            &auditor,
+            &state.workflow,
+            &todo.id,
+            ChildrenRemoval::Orphan,
+        )
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn trashed_todos(State(state): State<AppState>) -> Result<Json<Vec<TrashedTodo>>> {
+    let mut conn = state.acquire().await?;
+    let todos = todos::select_trashed_todos(&mut conn).await?;
+    Ok(Json(todos))
+}
+
+async fn restore_todo(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+    todos::restore_todo(&mut tx, &todo_id).await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Restore, &todo_id, before).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn purge_todo(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    let sha256s = attachments::select_todo_sha256s(&mut tx, &todo_id).await?;
+    let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+    todos::purge_todo(&mut tx, &todo_id).await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Purge, &todo_id, before).await?;
+    tx.commit().await?;
+    state
+        .attachments
+        .remove_unreferenced(&mut *state.acquire().await?, &sha256s)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn empty_trash(State(state): State<AppState>, auditor: Auditor) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    let sha256s = attachments::select_trashed_sha256s(&mut tx).await?;
+    for trashed_todo in todos::select_trashed_todos(&mut tx).await? {
+        let todo_id = trashed_todo.todo.id;
+        todos::purge_todo(&mut tx, &todo_id).await?;
+        let before = TodoRecord {
+            todo: trashed_todo.todo,
+            deleted_at: Some(trashed_todo.deleted_at),
+        };
+        audit::record_change(
+            &mut tx,
+            &auditor,
+            AuditAction::Purge,
+            &todo_id,
+            Some(before),
+        )
+        .await?;
+    }
+    tx.commit().await?;
+    state
+        .attachments
+        .remove_unreferenced(&mut *state.acquire().await?, &sha256s)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn todo_history(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TodoRevision>>> {
+    let mut conn = state.acquire().await?;
+    let revisions = revisions::select_revisions(&mut conn, &todo_id).await?;
+    if revisions.is_empty() {
+        return Err(Error::NotFound);
+    }
+    Ok(Json(revisions))
+}
+
+// Reverting records a new revision, so a revert can itself be reverted
+async fn revert_todo(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+    Json(revert): Json<RevertTodoRequest>,
 ) -> Result<StatusCode> {
-    let conn = state.pool.acquire().await?;
-    todos::remove_todo(conn, &todo_id).await?;
+    let mut tx = state.begin().await?;
+    let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+    let revision = revisions::select_revision(&mut tx, &todo_id, revert.revision).await?;
+    // The revisions without a state, or whose state left the workflow, restore the completion
+    let (completed, past_state) = match revision.state {
+        Some(past_state) if state.workflow.contains(&past_state) => (None, Some(past_state)),
+        _ => (Some(revision.completed), None),
+    };
+    let changes = UpdateTodoRequest {
+        content: Some(revision.content),
+        completed,
+        state: past_state,
+        reverting: true,
+        ..Default::default()
+    };
+    let next_occurrence = todos::update_todo(&mut tx, &todo_id, &changes, &state.workflow).await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Revert, &todo_id, before).await?;
+    record_next_occurrence(&mut tx, &auditor, next_occurrence).await?;
+    tx.commit().await?;
     Ok(StatusCode::NO_CONTENT)
 }
+
+async fn todo_children(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Todo>>> {
+    let mut conn = state.acquire().await?;
+    let todos = todos::select_children(&mut conn, &todo_id).await?;
+    Ok(Json(todos))
+}
+
+async fn todo_blockers(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Todo>>> {
+    let mut conn = state.acquire().await?;
+    let todos = dependencies::select_blockers(&mut conn, &todo_id).await?;
+    Ok(Json(todos))
+}
+
+async fn add_todo_blocker(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+    Json(dependency): Json<DependencyRequest>,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+    dependencies::add_dependency(&mut tx, &todo_id, &dependency.blocker_id).await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Update, &todo_id, before).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn remove_todo_blocker(
+    Path((todo_id, blocker_id)): Path<(Uuid, Uuid)>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+    dependencies::remove_dependency(&mut tx, &todo_id, &blocker_id).await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Update, &todo_id, before).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn todo_comments(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Comment>>> {
+    let mut conn = state.acquire().await?;
+    let comments = comments::select_comments(&mut conn, &todo_id).await?;
+    Ok(Json(comments))
+}
+
+async fn create_comment(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+    Json(comment): Json<CommentRequest>,
+) -> Result<(StatusCode, Json<CreatedResponse>)> {
+    let mut tx = state.begin().await?;
+    let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+    let comment_id =
+        comments::create_comment(&mut tx, &todo_id, &auditor.actor, &comment.content).await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Comment, &todo_id, before).await?;
+    tx.commit().await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedResponse { id: comment_id }),
+    ))
+}
+
+async fn update_comment(
+    Path((todo_id, comment_id)): Path<(Uuid, Uuid)>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+    Json(comment): Json<CommentRequest>,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+    comments::update_comment(
+        &mut tx,
+        &todo_id,
+        &comment_id,
+        &auditor.actor,
+        &comment.content,
+    )
+    .await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Comment, &todo_id, before).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn remove_comment(
+    Path((todo_id, comment_id)): Path<(Uuid, Uuid)>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+    comments::remove_comment(&mut tx, &todo_id, &comment_id, &auditor.actor).await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Comment, &todo_id, before).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn todo_attachments(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Attachment>>> {
+    let mut conn = state.acquire().await?;
+    let attachments = attachments::select_attachments(&mut conn, &todo_id).await?;
+    Ok(Json(attachments))
+}
+
+// Expects a single `file` field
+async fn upload_attachment(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+    mut multipart: Multipart,
+) -> Result<StatusCode> {
+    let field = multipart
+        .next_field()
+        .await
+        .map_err(|err| Error::Invalid(err.body_text()))?;
+    let Some(mut field) = field.filter(|field| field.name() == Some("file")) else {
+        return Err(Error::Invalid("the file field is missing".to_string()));
+    };
+    let filename = field.file_name().unwrap_or("attachment").to_string();
+    let content_type = field
+        .content_type()
+        .unwrap_or("application/octet-stream")
+        .to_string();
+
+    // Checked beforehand as well, not to receive the whole content for nothing
+    if !todos::is_live(&mut *state.acquire().await?, &todo_id).await? {
+        return Err(Error::NotFound);
+    }
+    let staged = state.attachments.stage(&mut field).await?;
+
+    let created = async {
+        let mut tx = state.begin().await?;
+        let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+        attachments::create_attachment(&mut tx, &todo_id, &staged, &filename, &content_type)
+            .await?;
+        audit::record_change(&mut tx, &auditor, AuditAction::Attach, &todo_id, before).await?;
+        state.attachments.store(&staged, tx).await
+    }
+    .await;
+    if let Err(err) = created {
+        state.attachments.discard(staged).await;
+        return Err(err);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn download_attachment(
+    Path((todo_id, attachment_id)): Path<(Uuid, Uuid)>,
+    State(state): State<AppState>,
+) -> Result<Response> {
+    let mut conn = state.acquire().await?;
+    let attachment = attachments::select_attachment(&mut conn, &todo_id, &attachment_id).await?;
+    drop(conn);
+
+    let file = File::open(state.attachments.content_path(&attachment.sha256)).await?;
+    let headers = [
+        (CONTENT_TYPE, attachment.content_type),
+        (CONTENT_LENGTH, attachment.size.to_string()),
+        (
+            CONTENT_DISPOSITION,
+            attachments::content_disposition(&attachment.filename),
+        ),
+    ];
+    Ok((headers, Body::from_stream(ReaderStream::new(file))).into_response())
+}
+
+async fn remove_attachment(
+    Path((todo_id, attachment_id)): Path<(Uuid, Uuid)>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+    let sha256 = attachments::remove_attachment(&mut tx, &todo_id, &attachment_id).await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Detach, &todo_id, before).await?;
+    tx.commit().await?;
+    state
+        .attachments
+        .remove_unreferenced(&mut *state.acquire().await?, &[sha256])
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn todo_time(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<TodoTime>> {
+    let mut conn = state.acquire().await?;
+    let time = time_tracking::select_todo_time(&mut conn, &todo_id).await?;
+    Ok(Json(time))
+}
+
+async fn create_time_entry(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+    Json(entry): Json<TimeEntryRequest>,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+    time_tracking::create_time_entry(
+        &mut tx,
+        &todo_id,
+        &auditor.actor,
+        entry.started_at,
+        entry.stopped_at,
+    )
+    .await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Track, &todo_id, before).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn start_timer(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+    time_tracking::start_timer(&mut tx, &todo_id, &auditor.actor).await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Track, &todo_id, before).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn stop_timer(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+    time_tracking::stop_timer(&mut tx, &todo_id, &auditor.actor).await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Track, &todo_id, before).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn time_report(
+    State(state): State<AppState>,
+    Query(report_filter): Query<TimeReportFilter>,
+) -> Result<Json<TimeReport>> {
+    let mut conn = state.acquire().await?;
+    let report = time_tracking::select_time_report(&mut conn, &report_filter).await?;
+    Ok(Json(report))
+}
+
+async fn add_todo_tag(
+    Path(todo_id): Path<Uuid>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+    Json(tag): Json<TagRequest>,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+    tags::add_todo_tag(&mut tx, &todo_id, &tag.name).await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Update, &todo_id, before).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn remove_todo_tag(
+    Path((todo_id, tag)): Path<(Uuid, String)>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    let before = todos::select_todo_record(&mut tx, &todo_id).await?;
+    tags::remove_todo_tag(&mut tx, &todo_id, &tag).await?;
+    audit::record_change(&mut tx, &auditor, AuditAction::Update, &todo_id, before).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn todos_workflow(State(state): State<AppState>) -> Json<Workflow> {
+    Json(Workflow::clone(&state.workflow))
+}
+
+async fn all_projects(
+    State(state): State<AppState>,
+    projects_filter: Option<Query<ProjectsFilterRequest>>,
+) -> Result<Json<Vec<Project>>> {
+    let projects_filter = projects_filter.unwrap_or_default();
+    let mut conn = state.acquire().await?;
+    let projects = projects::select_projects(&mut conn, projects_filter.archived).await?;
+    Ok(Json(projects))
+}
+
+async fn project(
+    Path(project_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<Project>> {
+    let mut conn = state.acquire().await?;
+    let project = projects::select_project(&mut conn, &project_id).await?;
+    Ok(Json(project))
+}
+
+async fn project_todos(
+    Path(project_id): Path<Uuid>,
+    State(state): State<AppState>,
+    todos_filters: Option<Query<TodosFilterRequest>>,
+) -> Result<Json<TodosResponse>> {
+    let todos_filters = TodosFilterRequest {
+        project: Some(project_id),
+        ..todos_filters.unwrap_or_default().0
+    };
+    let mut conn = state.acquire().await?;
+    projects::select_project(&mut conn, &project_id).await?;
+    let todos = todos::select_todos(&mut conn, &todos_filters).await?;
+    if todos_filters.tree {
+        return Ok(Json(TodosResponse::Tree(todos::build_tree(todos))));
+    }
+    Ok(Json(TodosResponse::List(todos)))
+}
+
+async fn create_project(
+    State(state): State<AppState>,
+    Json(new_project): Json<NewProjectRequest>,
+) -> Result<(StatusCode, Json<CreatedResponse>)> {
+    let mut tx = state.begin().await?;
+    let project_id = projects::create_project(&mut tx, &new_project).await?;
+    tx.commit().await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedResponse { id: project_id }),
+    ))
+}
+
+async fn update_project(
+    Path(project_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Json(changes): Json<UpdateProjectRequest>,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    projects::update_project(&mut tx, &project_id, &changes).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The todos of the project lose their project, which is audited as an update of each of them
+async fn remove_project(
+    Path(project_id): Path<Uuid>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    let todo_ids = projects::select_project_todo_ids(&mut tx, &project_id).await?;
+    let befores = select_todo_records(&mut tx, todo_ids).await?;
+    projects::remove_project(&mut tx, &project_id).await?;
+    record_todo_changes(&mut tx, &auditor, befores).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn all_tags(State(state): State<AppState>) -> Result<Json<Vec<Tag>>> {
+    let mut conn = state.acquire().await?;
+    let tags = tags::select_tags(&mut conn).await?;
+    Ok(Json(tags))
+}
+
+async fn create_tag(
+    State(state): State<AppState>,
+    Json(tag): Json<TagRequest>,
+) -> Result<StatusCode> {
+    let mut conn = state.acquire().await?;
+    tags::create_tag(&mut conn, &tag.name).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn rename_tag(
+    Path(tag): Path<String>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+    Json(new_tag): Json<TagRequest>,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    let todo_ids = tags::select_tagged_todo_ids(&mut tx, &tag).await?;
+    let befores = select_todo_records(&mut tx, todo_ids).await?;
+    tags::rename_tag(&mut tx, &tag, &new_tag.name).await?;
+    record_todo_changes(&mut tx, &auditor, befores).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn remove_tag(
+    Path(tag): Path<String>,
+    State(state): State<AppState>,
+    auditor: Auditor,
+) -> Result<StatusCode> {
+    let mut tx = state.begin().await?;
+    let todo_ids = tags::select_tagged_todo_ids(&mut tx, &tag).await?;
+    let befores = select_todo_records(&mut tx, todo_ids).await?;
+    tags::remove_tag(&mut tx, &tag).await?;
+    record_todo_changes(&mut tx, &auditor, befores).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The todos having a tag or belonging to a project, prior to renaming or removing it
+async fn select_todo_records(
+    conn: &mut SqliteConnection,
+    todo_ids: Vec<Uuid>,
+) -> Result<Vec<(Uuid, Option<TodoRecord>)>> {
+    let mut befores = Vec::new();
+    for todo_id in todo_ids {
+        let before = todos::select_todo_record(&mut *conn, &todo_id).await?;
+        befores.push((todo_id, before));
+    }
+
+    Ok(befores)
+}
+
+async fn record_todo_changes(
+    conn: &mut SqliteConnection,
+    auditor: &Auditor,
+    befores: Vec<(Uuid, Option<TodoRecord>)>,
+) -> Result<()> {
+    for (todo_id, before) in befores {
+        audit::record_change(&mut *conn, auditor, AuditAction::Update, &todo_id, before).await?;
+    }
+
+    Ok(())
+}
+
+// All the operations are executed in a single transaction, if one fails the whole batch is rolled back
+async fn batch_todos(
+    State(state): State<AppState>,
+    auditor: Auditor,
+    Json(batch): Json<BatchRequest>,
+) -> Result<Json<BatchResponse>> {
+    let mut tx = state.begin().await?;
+    let mut results = Vec::with_capacity(batch.operations.len());
+
+    for (index, operation) in batch.operations.into_iter().enumerate() {
+        match batch_operation(&mut tx, &auditor, &state.workflow, operation).await {
+            Ok(result) => results.push(result),
+            // Dropping the transaction rolls it back
+            Err(err) => {
+                return Err(Error::Batch {
+                    index,
+                    source: Box::new(err),
+                })
+            }
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(BatchResponse { results }))
+}
+
+async fn batch_operation(
+    conn: &mut SqliteConnection,
+    auditor: &Auditor,
+    workflow: &Workflow,
+    operation: BatchOperation,
+) -> Result<BatchOperationResult> {
+    match operation {
+        BatchOperation::Create(new_todo) => {
+            let id = todos::create_todo(&mut *conn, &new_todo, workflow).await?;
+            audit::record_change(conn, auditor, AuditAction::Create, &id, None).await?;
+            Ok(BatchOperationResult::Created { id })
+        }
+        BatchOperation::Update { id, changes } => {
+            let before = todos::select_todo_record(&mut *conn, &id).await?;
+            let next_occurrence = todos::update_todo(&mut *conn, &id, &changes, workflow).await?;
+            audit::record_change(&mut *conn, auditor, AuditAction::Update, &id, before).await?;
+            record_next_occurrence(conn, auditor, next_occurrence).await?;
+            Ok(BatchOperationResult::Updated { id })
+        }
+        BatchOperation::Delete { id, children } => {
+            remove_todo_with_children(conn, auditor, workflow, &id, children).await?;
+            Ok(BatchOperationResult::Deleted { id })
+        }
+    }
+}
+
+async fn audit_entries(
+    State(state): State<AppState>,
+    Query(audit_filter): Query<AuditFilter>,
+) -> Result<Json<Vec<AuditEntry>>> {
+    let mut conn = state.acquire().await?;
+    let entries = audit::select_audit_entries(&mut conn, &audit_filter).await?;
+    Ok(Json(entries))
+}
+
+/// The usernames and addresses currently locked out
+async fn lockouts(State(state): State<AppState>) -> Json<Vec<Lockout>> {
+    Json(state.login_guard.lockouts(Utc::now()))
+}
+
+/// Forgets the failed authentications of a username or address, lifting its lockout or backoff
+async fn clear_lockout(
+    State(state): State<AppState>,
+    auditor: Auditor,
+    Json(request): Json<ClearLockoutRequest>,
+) -> Result<StatusCode> {
+    let subject = Subject::parse(request.kind, &request.subject)?;
+    if !state.login_guard.clear(&subject) {
+        return Err(Error::NotFound);
+    }
+    let mut conn = state.acquire().await?;
+    lockouts::record_clearing(&mut conn, &auditor, &subject).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn lockout_entries(State(state): State<AppState>) -> Result<Json<Vec<LockoutEntry>>> {
+    let mut conn = state.acquire().await?;
+    let entries = lockouts::select_lockout_entries(&mut conn).await?;
+    Ok(Json(entries))
+}
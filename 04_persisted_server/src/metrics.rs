@@ -0,0 +1,193 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use axum::{
+    extract::{MatchedPath, Request},
+    response::Response,
+};
+use prometheus::{
+    exponential_buckets, Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use sqlx::{Pool, Sqlite};
+use tower::{Layer, Service};
+
+use crate::errors::Result;
+
+/// Labels the requests that didn't match any route
+static UNMATCHED_ROUTE: &str = "unmatched";
+
+/// The metrics exposed in the Prometheus text format, cloning them shares the same registry
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    pool_connections: IntGaugeVec,
+    pool_max_connections: IntGauge,
+    pool_acquire_duration: Histogram,
+    todos: IntGauge,
+    completed_todos: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new();
+
+        let requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "Number of handled requests"),
+            &["method", "route", "status"],
+        )?;
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time to produce the response headers",
+            ),
+            &["method", "route", "status"],
+        )?;
+        let pool_connections = IntGaugeVec::new(
+            Opts::new("db_pool_connections", "Number of open database connections"),
+            &["state"],
+        )?;
+        let pool_max_connections = IntGauge::new(
+            "db_pool_max_connections",
+            "Maximum number of database connections",
+        )?;
+        let pool_acquire_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "db_pool_acquire_duration_seconds",
+                "Time waited for a database connection",
+            )
+            // From 100µs to about 1.6s, the acquire timeout being 1s
+            .buckets(exponential_buckets(0.0001, 4.0, 8)?),
+        )?;
+        let todos = IntGauge::new("todos", "Number of todos not in the trash")?;
+        let completed_todos = IntGauge::new(
+            "todos_completed",
+            "Number of completed todos not in the trash",
+        )?;
+
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(request_duration.clone()))?;
+        registry.register(Box::new(pool_connections.clone()))?;
+        registry.register(Box::new(pool_max_connections.clone()))?;
+        registry.register(Box::new(pool_acquire_duration.clone()))?;
+        registry.register(Box::new(todos.clone()))?;
+        registry.register(Box::new(completed_todos.clone()))?;
+
+        Ok(Self {
+            registry,
+            requests,
+            request_duration,
+            pool_connections,
+            pool_max_connections,
+            pool_acquire_duration,
+            todos,
+            completed_todos,
+        })
+    }
+
+    pub fn observe_acquire(&self, started_at: Instant) {
+        self.pool_acquire_duration
+            .observe(started_at.elapsed().as_secs_f64());
+    }
+
+    /// The pool and todos gauges are refreshed on every scrape
+    pub async fn render(&self, pool: &Pool<Sqlite>) -> Result<String> {
+        let size = i64::from(pool.size());
+        let idle = i64::try_from(pool.num_idle())?;
+        self.pool_connections.with_label_values(&["idle"]).set(idle);
+        self.pool_connections
+            .with_label_values(&["in_use"])
+            .set(size - idle);
+        self.pool_max_connections
+            .set(i64::from(pool.options().get_max_connections()));
+
+        let (todos, completed_todos): (i64, i64) = sqlx::query_as(
+            "SELECT COUNT(*), COALESCE(SUM(completed), 0) FROM todos WHERE deleted_at IS NULL",
+        )
+        .fetch_one(pool)
+        .await?;
+        self.todos.set(todos);
+        self.completed_todos.set(completed_todos);
+
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+/// Counts and times the requests, labelled by route pattern rather than by path so that the
+/// number of series stays bounded
+#[derive(Debug, Clone)]
+pub struct MetricsLayer {
+    metrics: Metrics,
+}
+
+impl MetricsLayer {
+    pub fn new(metrics: Metrics) -> Self {
+        Self { metrics }
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService {
+            inner,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MetricsService<S> {
+    inner: S,
+    metrics: Metrics,
+}
+
+impl<S, B> Service<Request> for MetricsService<S>
+where
+    S: Service<Request, Response = Response<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<B>;
+    type Error = S::Error;
+    type Future =
+        Pin<Box<dyn Future<Output = std::result::Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<std::result::Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let started_at = Instant::now();
+        let method = request.method().to_string();
+        let route = request
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or(UNMATCHED_ROUTE, MatchedPath::as_str)
+            .to_string();
+        let metrics = self.metrics.clone();
+        let response = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = response.await?;
+            let status = response.status().as_u16().to_string();
+            let labels = [method.as_str(), route.as_str(), status.as_str()];
+            metrics.requests.with_label_values(&labels).inc();
+            metrics
+                .request_duration
+                .with_label_values(&labels)
+                .observe(started_at.elapsed().as_secs_f64());
+
+            Ok(response)
+        })
+    }
+}
@@ -1,23 +1,237 @@
-use axum::{extract::Request, http::StatusCode, middleware::Next, response::Response};
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use axum::{
+    body::{to_bytes, Body, HttpBody},
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER},
+        HeaderName, HeaderValue, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
 use axum_extra::{
     headers::{authorization::Basic, Authorization},
     TypedHeader,
 };
+use chrono::Utc;
+use tower_http::request_id::RequestId;
+use tracing::{error, field, info, info_span, warn, Span};
 
 static ALLOWED_USERNAME: &str = "admin";
 static ALLOWED_PASSWORD: &str = "admin";
 
-use crate::errors::Result;
+/// The request id is only echoed in the error bodies up to this size
+static ERROR_BODY_LIMIT: usize = 64 * 1024;
+
+use crate::{
+    errors::Result,
+    lockouts::{self, Lockout},
+    AppState,
+};
+
+/// The authenticated user, inserted in the request extensions by the `auth` middleware
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CurrentUser {
+    pub username: String,
+    pub admin: bool,
+}
+
+/// The address of the client, inserted in the request extensions by the `client_address`
+/// middleware when it's known
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientAddress(pub IpAddr);
+
+/// Takes the address of the client from the last address of the header set by the reverse
+/// proxy, when there is one, or from the peer otherwise
+pub async fn client_address(
+    State(header): State<Option<HeaderName>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let address = match &header {
+        Some(header) => request
+            .headers()
+            .get_all(header)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .last()
+            .and_then(|address| address.trim().parse().ok()),
+        None => request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip()),
+    };
+    if let Some(address) = address {
+        request.extensions_mut().insert(ClientAddress(address));
+    }
 
+    next.run(request).await
+}
+
+/// The username and the client address that failed to authenticate recently are refused for a
+/// while, see `LoginGuard`
 pub async fn auth(
+    State(state): State<AppState>,
     TypedHeader(auth): TypedHeader<Authorization<Basic>>,
-    request: Request,
+    mut request: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
+) -> Response {
+    let address = request
+        .extensions()
+        .get::<ClientAddress>()
+        .map(|ClientAddress(address)| *address);
+    let subjects = state.login_guard.subjects(auth.username(), address);
+    let now = Utc::now();
+    if let Some(blocked_until) = state.login_guard.blocked_until(&subjects, now) {
+        // Rounded up, not to have the clients retry too early
+        let retry_after = ((blocked_until - now).num_milliseconds() + 999) / 1000;
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(RETRY_AFTER, retry_after)],
+            format!("too many failed authentications, retry in {retry_after}s"),
+        )
+            .into_response();
+    }
+
     // Obviously not production code
     if auth.username() != ALLOWED_USERNAME || auth.password() != ALLOWED_PASSWORD {
-        return Err(StatusCode::UNAUTHORIZED);
+        let lockouts = state.login_guard.record_failure(&subjects, now);
+        if !lockouts.is_empty() {
+            let request_id = request
+                .extensions()
+                .get::<RequestId>()
+                .and_then(|request_id| request_id.header_value().to_str().ok());
+            if let Err(err) = record_lockouts(&state, &lockouts, request_id).await {
+                error!("couldn't record the lockouts: {err}");
+            }
+        }
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    state.login_guard.record_success(auth.username());
+
+    request.extensions_mut().insert(CurrentUser {
+        username: auth.username().to_string(),
+        admin: true,
+    });
+
+    next.run(request).await
+}
+
+async fn record_lockouts(
+    state: &AppState,
+    lockouts: &[Lockout],
+    request_id: Option<&str>,
+) -> Result<()> {
+    let mut conn = state.acquire().await?;
+    for lockout in lockouts {
+        warn!(
+            kind = ?lockout.subject.kind,
+            subject = lockout.subject.value,
+            failures = lockout.failures,
+            "locked out until {}",
+            lockout.locked_until
+        );
+        lockouts::record_lockout(&mut conn, lockout, request_id).await?;
+    }
+
+    Ok(())
+}
+
+/// Must be layered after the `auth` middleware
+pub async fn admin(request: Request, next: Next) -> Result<Response, StatusCode> {
+    let is_admin = request
+        .extensions()
+        .get::<CurrentUser>()
+        .is_some_and(|user| user.admin);
+    if !is_admin {
+        return Err(StatusCode::FORBIDDEN);
     }
 
     Ok(next.run(request).await)
 }
+
+/// The span of a request, its status and latency are recorded by `record_response`.
+/// Must be layered after the `SetRequestIdLayer`.
+pub fn request_span(request: &Request) -> Span {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(MatchedPath::as_str);
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|request_id| request_id.header_value().to_str().ok());
+
+    let span = info_span!(
+        "request",
+        method = %request.method(),
+        route,
+        request_id,
+        status = field::Empty,
+        latency = field::Empty,
+    );
+    #[cfg(feature = "otel")]
+    crate::otel::set_remote_parent(&span, request.headers());
+
+    span
+}
+
+pub fn record_response(response: &Response, latency: Duration, span: &Span) {
+    // Signed, the unsigned integers being exported as strings by OpenTelemetry
+    span.record("status", i64::from(response.status().as_u16()));
+    span.record("latency", field::debug(latency));
+    info!("responded");
+}
+
+/// Appends the request id to the plain text body of the error responses, for the users to report it.
+/// Must be layered after the `SetRequestIdLayer`.
+pub async fn echo_request_id(request: Request, next: Next) -> Response {
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|request_id| request_id.header_value().to_str().ok())
+        .map(ToString::to_string);
+    let response = next.run(request).await;
+
+    let Some(request_id) = request_id else {
+        return response;
+    };
+    let is_error = response.status().is_client_error() || response.status().is_server_error();
+    let is_text = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .map_or(true, |content_type| {
+            content_type.as_bytes().starts_with(b"text/plain")
+        });
+    let is_small = response
+        .body()
+        .size_hint()
+        .upper()
+        .is_some_and(|size| size <= ERROR_BODY_LIMIT as u64);
+    if !(is_error && is_text && is_small) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = to_bytes(body, ERROR_BODY_LIMIT).await.unwrap_or_default();
+    let body = if body.is_empty() {
+        format!("request id: {request_id}")
+    } else {
+        format!(
+            "{}\nrequest id: {request_id}",
+            String::from_utf8_lossy(&body)
+        )
+    };
+    parts.headers.remove(CONTENT_LENGTH);
+    parts.headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; charset=utf-8"),
+    );
+
+    Response::from_parts(parts, Body::from(body))
+}
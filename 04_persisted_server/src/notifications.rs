@@ -0,0 +1,62 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{errors::Result, todos::Todo};
+
+/// Fired by the reminder scheduler when the reminder date of a todo is reached
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReminderEvent {
+    pub todo: Todo,
+    pub fired_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotificationSink {
+    /// The event is posted as JSON to the url
+    Webhook(String),
+}
+
+/// Logs the events and delivers them to all the configured sinks
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    client: reqwest::Client,
+    sinks: Vec<NotificationSink>,
+}
+
+impl Notifier {
+    pub fn new(sinks: Vec<NotificationSink>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            sinks,
+        }
+    }
+
+    pub async fn notify(&self, event: &ReminderEvent) {
+        info!(
+            "reminder for todo {} ({}) fired",
+            event.todo.id, event.todo.content
+        );
+
+        for sink in &self.sinks {
+            if let Err(err) = self.deliver(sink, event).await {
+                error!("couldn't deliver the reminder to {sink:?}: {err}");
+            }
+        }
+    }
+
+    async fn deliver(&self, sink: &NotificationSink, event: &ReminderEvent) -> Result<()> {
+        match sink {
+            NotificationSink::Webhook(url) => {
+                self.client
+                    .post(url)
+                    .json(event)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+        }
+
+        Ok(())
+    }
+}
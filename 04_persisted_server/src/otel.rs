@@ -0,0 +1,178 @@
+use anyhow::Result;
+use axum::http::HeaderMap;
+use opentelemetry::{propagation::TextMapPropagator, trace::TracerProvider};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::{Protocol, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    trace::{SdkTracer, SdkTracerProvider},
+    Resource,
+};
+use tracing::{Span, Subscriber};
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::config::{OtelConfig, OtlpProtocol};
+
+/// Appended to the base url of the collector for the HTTP protocols
+static HTTP_TRACES_PATH: &str = "/v1/traces";
+
+/// The spans are exported in batches from a background thread, the gRPC exporter must be built
+/// within the Tokio runtime
+pub fn tracer_provider(config: &OtelConfig, endpoint: &str) -> Result<SdkTracerProvider> {
+    let http_endpoint = format!("{}{HTTP_TRACES_PATH}", endpoint.trim_end_matches('/'));
+    let exporter = match config.protocol {
+        OtlpProtocol::Grpc => SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build()?,
+        OtlpProtocol::HttpProtobuf => SpanExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpBinary)
+            .with_endpoint(http_endpoint)
+            .build()?,
+        OtlpProtocol::HttpJson => SpanExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpJson)
+            .with_endpoint(http_endpoint)
+            .build()?,
+    };
+    let resource = Resource::builder()
+        .with_service_name(config.service_name.clone())
+        .build();
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build())
+}
+
+/// Turns the `tracing` spans into OpenTelemetry spans
+pub fn layer<S>(tracer_provider: &SdkTracerProvider) -> OpenTelemetryLayer<S, SdkTracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer(env!("CARGO_PKG_NAME")))
+}
+
+/// Continues the trace of the caller given by the W3C `traceparent` header, if any
+pub fn set_remote_parent(span: &Span, headers: &HeaderMap) {
+    let parent = TraceContextPropagator::new().extract(&HeaderExtractor(headers));
+    // Only fails when the span is disabled
+    let _ = span.set_parent(parent);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::IntoFuture,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        body::Body,
+        extract::State,
+        http::{Request, StatusCode},
+        routing::{get, post},
+        Json, Router,
+    };
+    use serde_json::{json, Value};
+    use tokio::net::TcpListener;
+    use tower::ServiceExt;
+    use tower_http::trace::TraceLayer;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::middlewares::{record_response, request_span};
+
+    static TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    static PARENT_SPAN_ID: &str = "00f067aa0ba902b7";
+
+    /// The export requests received by the collector stub
+    type Exports = Arc<Mutex<Vec<Value>>>;
+
+    /// Serves the OTLP/HTTP JSON traces endpoint on a random port, returning its base url
+    async fn collector_stub(exports: Exports) -> String {
+        let collector = Router::new()
+            .route(
+                HTTP_TRACES_PATH,
+                post(
+                    |State(exports): State<Exports>, Json(export): Json<Value>| async move {
+                        exports.lock().unwrap().push(export);
+                        Json(json!({}))
+                    },
+                ),
+            )
+            .with_state(exports);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, collector).into_future());
+
+        format!("http://{address}")
+    }
+
+    fn attribute<'a>(attributes: &'a Value, key: &str) -> Option<&'a Value> {
+        attributes
+            .as_array()?
+            .iter()
+            .find(|attribute| attribute["key"] == key)
+            .map(|attribute| &attribute["value"])
+    }
+
+    // The batches are exported from a background thread while the test thread blocks on the flush
+    #[tokio::test(flavor = "multi_thread")]
+    async fn exports_the_request_spans_within_the_remote_trace() {
+        let exports = Exports::default();
+        let endpoint = collector_stub(exports.clone()).await;
+        let config = OtelConfig {
+            endpoint: Some(endpoint.clone()),
+            protocol: OtlpProtocol::HttpJson,
+            service_name: "todos-test".to_string(),
+        };
+        let tracer_provider = tracer_provider(&config, &endpoint).unwrap();
+        let subscriber = tracing_subscriber::registry().with(layer(&tracer_provider));
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let app = Router::new().route("/todos/:id", get(|| async {})).layer(
+            TraceLayer::new_for_http()
+                .make_span_with(request_span)
+                .on_response(record_response),
+        );
+        let request = Request::get("/todos/1")
+            .header("traceparent", format!("00-{TRACE_ID}-{PARENT_SPAN_ID}-01"))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        // The span ends with the response body
+        drop(response);
+        tracer_provider.force_flush().unwrap();
+
+        let exports = exports.lock().unwrap();
+        let resource_spans: Vec<&Value> = exports
+            .iter()
+            .flat_map(|export| export["resourceSpans"].as_array().unwrap())
+            .collect();
+        let service_name = attribute(&resource_spans[0]["resource"]["attributes"], "service.name");
+        assert_eq!(service_name, Some(&json!({ "stringValue": "todos-test" })));
+
+        let spans: Vec<&Value> = resource_spans
+            .iter()
+            .flat_map(|resource_spans| resource_spans["scopeSpans"].as_array().unwrap())
+            .flat_map(|scope_spans| scope_spans["spans"].as_array().unwrap())
+            .collect();
+        assert_eq!(spans.len(), 1);
+        let span = spans[0];
+        assert_eq!(span["name"], "request");
+        assert_eq!(span["traceId"], TRACE_ID);
+        assert_eq!(span["parentSpanId"], PARENT_SPAN_ID);
+        assert_eq!(
+            attribute(&span["attributes"], "route"),
+            Some(&json!({ "stringValue": "/todos/:id" }))
+        );
+        assert_eq!(
+            attribute(&span["attributes"], "status"),
+            Some(&json!({ "intValue": "200" }))
+        );
+    }
+}
@@ -1,26 +1,224 @@
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use uuid::Uuid;
 
-use crate::todos::TodosFilter;
+use crate::{
+    lockouts::SubjectKind,
+    recurrence::RecurrenceRule,
+    todos::{ChildrenRemoval, Priority, Todo, TodoNode, TodosFilter, TodosSort},
+};
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TodosFilterRequest {
+    #[serde(default)]
     pub filter: TodosFilter,
+    #[serde(rename = "due-before")]
+    pub due_before: Option<DateTime<Utc>>,
+    #[serde(rename = "due-after")]
+    pub due_after: Option<DateTime<Utc>>,
+    pub state: Option<String>,
+    pub priority: Option<Priority>,
+    pub project: Option<Uuid>,
+    pub tag: Option<String>,
+    pub sort: Option<TodosSort>,
+    /// Nests the todos under their parent
+    #[serde(default)]
+    pub tree: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TodosResponse {
+    List(Vec<Todo>),
+    Tree(Vec<TodoNode>),
 }
 
-impl Default for TodosFilterRequest {
-    fn default() -> Self {
-        Self {
-            filter: TodosFilter::All,
-        }
-    }
+/// The identifier of a created resource
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreatedResponse {
+    pub id: Uuid,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoveTodosRequest {
+    pub filter: TodosFilter,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct NewTodoRequest {
     pub content: String,
+    pub due_at: Option<DateTime<Utc>>,
+    pub remind_at: Option<DateTime<Utc>>,
+    pub priority: Option<Priority>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub parent_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
+    pub recurrence: Option<RecurrenceRule>,
+}
+
+/// Only the provided fields are updated, `null` clears the due and reminder dates, the priority,
+/// the parent, the project and the recurrence, the tags replace the existing ones
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(clippy::option_option)]
+pub struct UpdateTodoRequest {
+    pub content: Option<String>,
+    pub completed: Option<bool>,
+    /// Must be allowed by the workflow, unlike the completion
+    pub state: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub due_at: Option<Option<DateTime<Utc>>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub remind_at: Option<Option<DateTime<Utc>>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub priority: Option<Option<Priority>>,
+    pub tags: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub parent_id: Option<Option<Uuid>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub project_id: Option<Option<Uuid>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub recurrence: Option<Option<RecurrenceRule>>,
+    /// Completes the todo even if it is blocked
+    #[serde(default)]
+    pub force: bool,
+    /// Set when reverting, the past state is restored whatever the transitions of the workflow
+    #[serde(skip)]
+    pub reverting: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TodoCompletedRequest {
     pub completed: bool,
+    /// Completes the children too, recursively, when completing the todo
+    #[serde(default)]
+    pub cascade: bool,
+    /// Completes the todo even if it is blocked
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransitionRequest {
+    pub state: String,
+    /// Moves the todo to the terminal state even if it is blocked
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Places the todo right after `after` and/or right before `before`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MoveTodoRequest {
+    pub after: Option<Uuid>,
+    pub before: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DependencyRequest {
+    pub blocker_id: Uuid,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoveTodoRequest {
+    #[serde(default)]
+    pub children: ChildrenRemoval,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommentRequest {
+    pub content: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeEntryRequest {
+    pub started_at: DateTime<Utc>,
+    pub stopped_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClearLockoutRequest {
+    pub kind: SubjectKind,
+    /// A username or an address
+    pub subject: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProjectsFilterRequest {
+    /// Lists the archived projects too
+    #[serde(default)]
+    pub archived: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewProjectRequest {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+/// Only the provided fields are updated
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdateProjectRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub archived: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RevertTodoRequest {
+    pub revision: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchRequest {
+    pub operations: Vec<BatchOperation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op")]
+pub enum BatchOperation {
+    #[serde(rename = "create")]
+    Create(NewTodoRequest),
+    #[serde(rename = "update")]
+    Update {
+        id: Uuid,
+        #[serde(flatten)]
+        changes: UpdateTodoRequest,
+    },
+    #[serde(rename = "delete")]
+    Delete {
+        id: Uuid,
+        #[serde(default)]
+        children: ChildrenRemoval,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op")]
+pub enum BatchOperationResult {
+    #[serde(rename = "create")]
+    Created { id: Uuid },
+    #[serde(rename = "update")]
+    Updated { id: Uuid },
+    #[serde(rename = "delete")]
+    Deleted { id: Uuid },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchResponse {
+    pub results: Vec<BatchOperationResult>,
+}
+
+/// Distinguishes a field set to `null` (`Some(None)`) from a missing one (`None`)
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
 }
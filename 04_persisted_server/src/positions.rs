@@ -0,0 +1,264 @@
+/// The digits of the positions, in ASCII order so that the database sorts the positions as expected
+static DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// The integer part that can't be decremented
+static SMALLEST_INTEGER: &[u8] = b"A00000000000000000000000000";
+
+/// Fractional indexing: returns a position sorting after `after` and before `before`, so that
+/// moving a todo doesn't touch the others. A missing bound means the start or the end of the list,
+/// `None` is returned if `after` doesn't sort before `before`.
+///
+/// A position is made of an integer part, whose length is given by its first character,
+/// followed by a fraction part, so that appending to the list keeps the positions short.
+pub fn key_between(after: Option<&str>, before: Option<&str>) -> Option<String> {
+    let key = match (after.map(str::as_bytes), before.map(str::as_bytes)) {
+        (None, None) => vec![b'a', DIGITS[0]],
+        (None, Some(before)) => {
+            let (integer, fraction) = split_key(before)?;
+            if integer == SMALLEST_INTEGER {
+                // Nothing sorts before the smallest integer without a fraction
+                if fraction.is_empty() {
+                    return None;
+                }
+                [integer, &midpoint(b"", Some(fraction))].concat()
+            } else if fraction.is_empty() {
+                decrement_integer(integer)?
+            } else {
+                integer.to_vec()
+            }
+        }
+        (Some(after), None) => {
+            let (integer, fraction) = split_key(after)?;
+            increment_integer(integer)
+                .unwrap_or_else(|| [integer, &midpoint(fraction, None)].concat())
+        }
+        (Some(after), Some(before)) => {
+            if after >= before {
+                return None;
+            }
+            let (integer_after, fraction_after) = split_key(after)?;
+            let (integer_before, fraction_before) = split_key(before)?;
+            if integer_after == integer_before {
+                [
+                    integer_after,
+                    &midpoint(fraction_after, Some(fraction_before)),
+                ]
+                .concat()
+            } else {
+                match increment_integer(integer_after) {
+                    Some(integer) if integer.as_slice() < before => integer,
+                    _ => [integer_after, &midpoint(fraction_after, None)].concat(),
+                }
+            }
+        }
+    };
+
+    Some(key.into_iter().map(char::from).collect())
+}
+
+/// Returns the integer and the fraction parts of the position, `None` if it's malformed
+fn split_key(key: &[u8]) -> Option<(&[u8], &[u8])> {
+    let head = *key.first()?;
+    let length = match head {
+        b'a'..=b'z' => usize::from(head - b'a') + 2,
+        b'A'..=b'Z' => usize::from(b'Z' - head) + 2,
+        _ => return None,
+    };
+
+    if key.len() < length || !key[1..].iter().all(|digit| DIGITS.contains(digit)) {
+        return None;
+    }
+    let (integer, fraction) = key.split_at(length);
+    (fraction.last() != Some(&DIGITS[0])).then_some((integer, fraction))
+}
+
+fn increment_integer(integer: &[u8]) -> Option<Vec<u8>> {
+    let (&head, digits) = integer.split_first()?;
+    let mut digits = digits.to_vec();
+    for digit in digits.iter_mut().rev() {
+        let index = digit_index(*digit) + 1;
+        if index < DIGITS.len() {
+            *digit = DIGITS[index];
+            return Some([&[head], digits.as_slice()].concat());
+        }
+        *digit = DIGITS[0];
+    }
+
+    // All the digits overflowed, the length of the integer changes
+    match head {
+        b'Z' => Some(vec![b'a', DIGITS[0]]),
+        b'z' => None,
+        _ => {
+            let head = head + 1;
+            if head > b'a' {
+                digits.push(DIGITS[0]);
+            } else {
+                digits.pop();
+            }
+            Some([&[head], digits.as_slice()].concat())
+        }
+    }
+}
+
+fn decrement_integer(integer: &[u8]) -> Option<Vec<u8>> {
+    let last_digit = DIGITS[DIGITS.len() - 1];
+    let (&head, digits) = integer.split_first()?;
+    let mut digits = digits.to_vec();
+    for digit in digits.iter_mut().rev() {
+        let index = digit_index(*digit);
+        if index > 0 {
+            *digit = DIGITS[index - 1];
+            return Some([&[head], digits.as_slice()].concat());
+        }
+        *digit = last_digit;
+    }
+
+    // All the digits underflowed, the length of the integer changes
+    match head {
+        b'a' => Some(vec![b'Z', last_digit]),
+        b'A' => None,
+        _ => {
+            let head = head - 1;
+            if head < b'Z' {
+                digits.push(last_digit);
+            } else {
+                digits.pop();
+            }
+            Some([&[head], digits.as_slice()].concat())
+        }
+    }
+}
+
+/// Returns a fraction between `after` and `before`, a missing `before` meaning 1.
+/// The fractions never end with the first digit, so that there is always room before them.
+fn midpoint(after: &[u8], before: Option<&[u8]>) -> Vec<u8> {
+    let digit = |key: &[u8], index: usize| key.get(index).copied().unwrap_or(DIGITS[0]);
+
+    if let Some(before) = before {
+        let common = (0..before.len())
+            .take_while(|index| digit(after, *index) == before[*index])
+            .count();
+        if common > 0 {
+            return [
+                &before[..common],
+                midpoint(
+                    after.get(common..).unwrap_or_default(),
+                    Some(&before[common..]),
+                )
+                .as_slice(),
+            ]
+            .concat();
+        }
+    }
+
+    let digit_after = after.first().map_or(0, |digit| digit_index(*digit));
+    let digit_before = before.map_or(DIGITS.len(), |before| digit_index(before[0]));
+    // Malformed fractions can have their digits in the wrong order
+    if digit_before
+        .checked_sub(digit_after)
+        .is_some_and(|gap| gap > 1)
+    {
+        return vec![DIGITS[(digit_after + digit_before).div_ceil(2)]];
+    }
+
+    // The first digits are consecutive
+    match before {
+        Some(before) if before.len() > 1 => vec![before[0]],
+        _ => [
+            &[DIGITS[digit_after]],
+            midpoint(after.get(1..).unwrap_or_default(), None).as_slice(),
+        ]
+        .concat(),
+    }
+}
+
+fn digit_index(digit: u8) -> usize {
+    DIGITS
+        .iter()
+        .position(|candidate| *candidate == digit)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starts_the_list_in_the_middle() {
+        assert_eq!(key_between(None, None).as_deref(), Some("a0"));
+    }
+
+    #[test]
+    fn keeps_the_appended_and_prepended_positions_ordered() {
+        let mut last = key_between(None, None).unwrap();
+        for _ in 0..1000 {
+            let next = key_between(Some(&last), None).unwrap();
+            assert!(last < next, "{last} < {next}");
+            last = next;
+        }
+        let mut first = key_between(None, None).unwrap();
+        for _ in 0..1000 {
+            let previous = key_between(None, Some(&first)).unwrap();
+            assert!(previous < first, "{previous} < {first}");
+            first = previous;
+        }
+    }
+
+    #[test]
+    fn keeps_the_positions_inserted_between_ordered() {
+        let after = key_between(None, None).unwrap();
+        let mut before = key_between(Some(&after), None).unwrap();
+        for _ in 0..100 {
+            let position = key_between(Some(&after), Some(&before)).unwrap();
+            assert!(
+                after < position && position < before,
+                "{after} < {position} < {before}"
+            );
+            before = position;
+        }
+        let mut after = key_between(None, None).unwrap();
+        let before = key_between(Some(&after), None).unwrap();
+        for _ in 0..100 {
+            let position = key_between(Some(&after), Some(&before)).unwrap();
+            assert!(
+                after < position && position < before,
+                "{after} < {position} < {before}"
+            );
+            after = position;
+        }
+    }
+
+    #[test]
+    fn crosses_the_integer_boundaries() {
+        assert_eq!(key_between(Some("Zz"), None).as_deref(), Some("a0"));
+        assert_eq!(key_between(None, Some("a0")).as_deref(), Some("Zz"));
+        assert_eq!(key_between(Some("az"), None).as_deref(), Some("b00"));
+        assert_eq!(key_between(None, Some("b00")).as_deref(), Some("az"));
+
+        let largest = format!("z{}", "z".repeat(26));
+        let position = key_between(Some(&largest), None).unwrap();
+        assert!(largest < position);
+        let smallest = std::str::from_utf8(SMALLEST_INTEGER).unwrap();
+        assert_eq!(key_between(None, Some(smallest)), None);
+        let before = format!("{smallest}V");
+        let position = key_between(None, Some(&before)).unwrap();
+        assert!(position < before, "{position} < {before}");
+    }
+
+    #[test]
+    fn rejects_the_bounds_in_the_wrong_order() {
+        assert_eq!(key_between(Some("a1"), Some("a1")), None);
+        assert_eq!(key_between(Some("a2"), Some("a1")), None);
+    }
+
+    #[test]
+    fn rejects_the_malformed_positions() {
+        for key in ["", "!", "b0", "a", "a0!", "a00", "a1V0"] {
+            assert_eq!(key_between(Some(key), None), None, "{key}");
+            assert_eq!(key_between(None, Some(key)), None, "{key}");
+            assert_eq!(key_between(Some("Z0"), Some(key)), None, "{key}");
+        }
+        assert_eq!(key_between(Some("a0"), Some("a00")), None);
+        assert_eq!(key_between(Some("a0z"), Some("a0!")), None);
+    }
+}
@@ -0,0 +1,174 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, QueryBuilder, Sqlite, SqliteConnection};
+use uuid::Uuid;
+
+use crate::{
+    errors::{Error, Result},
+    payloads::{NewProjectRequest, UpdateProjectRequest},
+};
+
+/// The columns matching the `Project` fields, the progress being aggregated from the todos of the
+/// project that are not in the trash, see `PROJECT_TODOS`
+static PROJECT_COLUMNS: &str = concat!(
+    "projects.id, projects.name, projects.description, projects.archived, projects.created_at, ",
+    "COUNT(todos.id) AS total, ",
+    "COALESCE(SUM(todos.completed), 0) AS completed, ",
+    "COALESCE(SUM(todos.completed = 0 AND julianday(todos.due_at) < julianday('now')), 0) AS overdue",
+);
+
+static PROJECT_TODOS: &str =
+    "FROM projects LEFT JOIN todos ON todos.project_id = projects.id AND todos.deleted_at IS NULL";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct Project {
+    pub id: Uuid,
+    pub name: String,
+    pub description: String,
+    /// An archived project can't get new todos
+    pub archived: bool,
+    pub created_at: DateTime<Utc>,
+    #[sqlx(flatten)]
+    pub progress: ProjectProgress,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct ProjectProgress {
+    pub total: i64,
+    pub completed: i64,
+    /// Not completed and past their due date
+    pub overdue: i64,
+}
+
+/// The archived projects are only listed on demand
+pub async fn select_projects(conn: &mut SqliteConnection, archived: bool) -> Result<Vec<Project>> {
+    let mut query =
+        QueryBuilder::<Sqlite>::new(format!("SELECT {PROJECT_COLUMNS} {PROJECT_TODOS}"));
+    if !archived {
+        query.push(" WHERE projects.archived = 0");
+    }
+    query.push(" GROUP BY projects.id ORDER BY projects.name");
+
+    let projects = query.build_query_as().fetch_all(conn).await?;
+
+    Ok(projects)
+}
+
+pub async fn select_project(conn: &mut SqliteConnection, project_id: &Uuid) -> Result<Project> {
+    let query = format!(
+        "SELECT {PROJECT_COLUMNS} {PROJECT_TODOS} WHERE projects.id = ? GROUP BY projects.id"
+    );
+
+    let project = sqlx::query_as(&query)
+        .bind(project_id)
+        .fetch_optional(conn)
+        .await?;
+
+    project.ok_or(Error::NotFound)
+}
+
+pub async fn create_project(
+    conn: &mut SqliteConnection,
+    new_project: &NewProjectRequest,
+) -> Result<Uuid> {
+    check_name(&new_project.name)?;
+
+    let project_id = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO projects (id, name, description, archived, created_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(project_id)
+    .bind(&new_project.name)
+    .bind(&new_project.description)
+    .bind(false)
+    .bind(Utc::now())
+    .execute(conn)
+    .await?;
+
+    Ok(project_id)
+}
+
+/// Only the provided fields are updated
+pub async fn update_project(
+    conn: &mut SqliteConnection,
+    project_id: &Uuid,
+    changes: &UpdateProjectRequest,
+) -> Result<()> {
+    if let Some(name) = &changes.name {
+        check_name(name)?;
+    }
+
+    // `id = id` is a no-op that lets every change be prefixed with a comma
+    let mut query = QueryBuilder::<Sqlite>::new("UPDATE projects SET id = id");
+    if let Some(name) = &changes.name {
+        query.push(", name = ").push_bind(name);
+    }
+    if let Some(description) = &changes.description {
+        query.push(", description = ").push_bind(description);
+    }
+    if let Some(archived) = changes.archived {
+        query.push(", archived = ").push_bind(archived);
+    }
+    query.push(" WHERE id = ").push_bind(project_id);
+
+    let result = query.build().execute(conn).await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound);
+    }
+
+    Ok(())
+}
+
+/// The todos of the project are kept, without project
+pub async fn remove_project(conn: &mut SqliteConnection, project_id: &Uuid) -> Result<()> {
+    let result = sqlx::query("DELETE FROM projects WHERE id = ?")
+        .bind(project_id)
+        .execute(conn)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound);
+    }
+
+    Ok(())
+}
+
+/// The todos of the project, including the ones in the trash
+pub async fn select_project_todo_ids(
+    conn: &mut SqliteConnection,
+    project_id: &Uuid,
+) -> Result<Vec<Uuid>> {
+    let todo_ids = sqlx::query_scalar("SELECT id FROM todos WHERE project_id = ?")
+        .bind(project_id)
+        .fetch_all(conn)
+        .await?;
+
+    Ok(todo_ids)
+}
+
+fn check_name(name: &str) -> Result<()> {
+    if name.trim().is_empty() {
+        return Err(Error::Invalid("a project name can't be blank".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Todos can only be assigned to existing projects that are not archived
+pub async fn check_project(conn: &mut SqliteConnection, project_id: &Uuid) -> Result<()> {
+    let archived: Option<bool> = sqlx::query_scalar("SELECT archived FROM projects WHERE id = ?")
+        .bind(project_id)
+        .fetch_optional(conn)
+        .await?;
+
+    match archived {
+        None => Err(Error::Invalid(format!(
+            "the project {project_id} doesn't exist"
+        ))),
+        Some(true) => Err(Error::Conflict(format!(
+            "the project {project_id} is archived"
+        ))),
+        Some(false) => Ok(()),
+    }
+}
@@ -0,0 +1,378 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{
+        header::{HeaderName, AUTHORIZATION, RETRY_AFTER},
+        HeaderMap, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    config::{BudgetConfig, RateLimitConfig},
+    middlewares::{ClientAddress, CurrentUser},
+};
+
+static RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+static RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+static RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// Whose budget a request is taken from. The requests whose client address isn't known, such as
+/// the ones of the peers of a Unix socket without a client address header, aren't limited until
+/// they are authenticated, rather than all sharing a budget.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Client {
+    User(String),
+    Ip(IpAddr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Budget {
+    /// The safe methods, such as `GET`
+    Reads,
+    Writes,
+}
+
+impl Budget {
+    fn of(request: &Request) -> Self {
+        if request.method().is_safe() {
+            Self::Reads
+        } else {
+            Self::Writes
+        }
+    }
+}
+
+/// Holds up to `burst` tokens, continuously refilled at `per_minute`, each request taking one
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    fn full(budget: BudgetConfig, now: Instant) -> Self {
+        Self {
+            tokens: f64::from(budget.burst),
+            updated_at: now,
+        }
+    }
+
+    fn tokens_at(&self, budget: BudgetConfig, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        let refilled = elapsed.as_secs_f64() * tokens_per_sec(budget);
+
+        (self.tokens + refilled).min(f64::from(budget.burst))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Decision {
+    allowed: bool,
+    limit: u32,
+    remaining: u32,
+    /// Until the bucket is full again
+    reset: Duration,
+    /// Until the next token, when not allowed
+    retry_after: Duration,
+}
+
+/// The token buckets of the clients, kept in memory, cloning it shares the same buckets
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    config: Arc<RateLimitConfig>,
+    buckets: Arc<Mutex<HashMap<(Client, Budget), Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            buckets: Arc::default(),
+        }
+    }
+
+    fn budget(&self, budget: Budget) -> BudgetConfig {
+        match budget {
+            Budget::Reads => self.config.reads,
+            Budget::Writes => self.config.writes,
+        }
+    }
+
+    fn take(&self, client: Client, budget: Budget, now: Instant) -> Decision {
+        self.decide(client, budget, now, true)
+    }
+
+    /// Tells whether a token could be taken, without taking it
+    fn peek(&self, client: Client, budget: Budget, now: Instant) -> Decision {
+        self.decide(client, budget, now, false)
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn decide(&self, client: Client, budget: Budget, now: Instant, take: bool) -> Decision {
+        let config = self.budget(budget);
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets
+            .entry((client, budget))
+            .or_insert_with(|| Bucket::full(config, now));
+        bucket.tokens = bucket.tokens_at(config, now);
+        bucket.updated_at = now;
+        let allowed = bucket.tokens >= 1.0;
+        if allowed && take {
+            bucket.tokens -= 1.0;
+        }
+
+        Decision {
+            allowed,
+            limit: config.burst,
+            // Between 0 and `burst`
+            remaining: bucket.tokens.floor() as u32,
+            reset: Duration::from_secs_f64(
+                (f64::from(config.burst) - bucket.tokens) / tokens_per_sec(config),
+            ),
+            retry_after: Duration::from_secs_f64(
+                (1.0 - bucket.tokens).max(0.0) / tokens_per_sec(config),
+            ),
+        }
+    }
+
+    /// Drops the buckets that are full again, as a new bucket would be, returning how many were
+    /// dropped
+    pub fn evict(&self, now: Instant) -> usize {
+        let mut buckets = self.buckets.lock().unwrap();
+        let len = buckets.len();
+        buckets.retain(|(_, budget), bucket| {
+            let config = self.budget(*budget);
+            bucket.tokens_at(config, now) < f64::from(config.burst)
+        });
+
+        len - buckets.len()
+    }
+}
+
+fn tokens_per_sec(budget: BudgetConfig) -> f64 {
+    f64::from(budget.per_minute) / 60.0
+}
+
+/// Takes a token from the budget of the authenticated user, or of the client address for the
+/// routes not requiring authentication, answering 429 when it's empty
+pub async fn rate_limit(
+    State(limiter): State<RateLimiter>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let client = match request.extensions().get::<CurrentUser>() {
+        Some(user) => Client::User(user.username.clone()),
+        None => match request.extensions().get::<ClientAddress>() {
+            Some(ClientAddress(address)) => Client::Ip(*address),
+            None => return next.run(request).await,
+        },
+    };
+    if !limiter.config.enabled {
+        return next.run(request).await;
+    }
+
+    let decision = limiter.take(client, Budget::of(&request), Instant::now());
+    let mut response = if decision.allowed {
+        next.run(request).await
+    } else {
+        too_many_requests(&decision)
+    };
+    insert_headers(response.headers_mut(), &decision);
+
+    response
+}
+
+/// Layered before the `auth` middleware, takes a token from the budget of the client address for
+/// the requests without credentials or failing to authenticate only. The users sharing an address,
+/// such as behind a NAT, keep a budget each once authenticated. An address whose budget is empty
+/// is answered 429 before authenticating.
+pub async fn rate_limit_failures(
+    State(limiter): State<RateLimiter>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(ClientAddress(address)) = request.extensions().get::<ClientAddress>().copied() else {
+        return next.run(request).await;
+    };
+    if !limiter.config.enabled {
+        return next.run(request).await;
+    }
+
+    let client = Client::Ip(address);
+    let budget = Budget::of(&request);
+    let decision = limiter.peek(client.clone(), budget, Instant::now());
+    if !decision.allowed {
+        let mut response = too_many_requests(&decision);
+        insert_headers(response.headers_mut(), &decision);
+        return response;
+    }
+    let authenticating = request.headers().contains_key(AUTHORIZATION);
+    let mut response = next.run(request).await;
+    if !authenticating || response.status() == StatusCode::UNAUTHORIZED {
+        let decision = limiter.take(client, budget, Instant::now());
+        insert_headers(response.headers_mut(), &decision);
+    }
+
+    response
+}
+
+fn too_many_requests(decision: &Decision) -> Response {
+    let retry_after = ceil_secs(decision.retry_after);
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(RETRY_AFTER, retry_after)],
+        format!("too many requests, retry in {retry_after}s"),
+    )
+        .into_response()
+}
+
+fn insert_headers(headers: &mut HeaderMap, decision: &Decision) {
+    headers.insert(RATELIMIT_LIMIT.clone(), decision.limit.into());
+    headers.insert(RATELIMIT_REMAINING.clone(), decision.remaining.into());
+    headers.insert(RATELIMIT_RESET.clone(), ceil_secs(decision.reset).into());
+}
+
+/// The headers are in whole seconds, rounding down would have the clients retry too early
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::HeaderValue, middleware, routing::post, Extension, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            enabled: true,
+            reads: BudgetConfig {
+                burst: 2,
+                per_minute: 60,
+            },
+            writes: BudgetConfig {
+                burst: 1,
+                per_minute: 6,
+            },
+        })
+    }
+
+    fn user(username: &str) -> Client {
+        Client::User(username.to_string())
+    }
+
+    #[test]
+    fn refuses_the_requests_once_the_burst_is_spent() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        let first = limiter.take(user("alice"), Budget::Reads, now);
+        assert!(first.allowed);
+        assert_eq!((first.limit, first.remaining), (2, 1));
+        let second = limiter.take(user("alice"), Budget::Reads, now);
+        assert!(second.allowed);
+        assert_eq!(second.remaining, 0);
+        assert_eq!(second.reset, Duration::from_secs(2));
+
+        let third = limiter.take(user("alice"), Budget::Reads, now);
+        assert!(!third.allowed);
+        assert_eq!(third.retry_after, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn refills_the_buckets_over_time() {
+        let limiter = limiter();
+        let now = Instant::now();
+        assert!(limiter.take(user("alice"), Budget::Writes, now).allowed);
+        assert!(!limiter.take(user("alice"), Budget::Writes, now).allowed);
+
+        // A write every 10 seconds
+        let decision = limiter.take(user("alice"), Budget::Writes, now + Duration::from_secs(5));
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, Duration::from_secs(5));
+        assert!(
+            limiter
+                .take(user("alice"), Budget::Writes, now + Duration::from_secs(10))
+                .allowed
+        );
+    }
+
+    #[test]
+    fn keeps_a_budget_per_client_and_method() {
+        let limiter = limiter();
+        let now = Instant::now();
+        assert!(limiter.take(user("alice"), Budget::Writes, now).allowed);
+
+        assert!(!limiter.take(user("alice"), Budget::Writes, now).allowed);
+        assert!(limiter.take(user("alice"), Budget::Reads, now).allowed);
+        assert!(limiter.take(user("bob"), Budget::Writes, now).allowed);
+        assert!(
+            limiter
+                .take(Client::Ip([127, 0, 0, 1].into()), Budget::Writes, now)
+                .allowed
+        );
+    }
+
+    #[tokio::test]
+    async fn charges_the_addresses_for_the_failed_authentications_only() {
+        // Stands for the `auth` middleware, refusing the `wrong` credentials
+        let app = Router::new()
+            .route(
+                "/",
+                post(|headers: HeaderMap| async move {
+                    match headers.get(AUTHORIZATION).map(HeaderValue::as_bytes) {
+                        Some(b"wrong") => StatusCode::UNAUTHORIZED,
+                        Some(_) => StatusCode::OK,
+                        None => StatusCode::BAD_REQUEST,
+                    }
+                }),
+            )
+            .layer(middleware::from_fn_with_state(
+                limiter(),
+                rate_limit_failures,
+            ))
+            .layer(Extension(ClientAddress([127, 0, 0, 1].into())));
+        let status = |authorization: Option<&'static str>| {
+            let mut request = Request::post("/");
+            if let Some(authorization) = authorization {
+                request = request.header(AUTHORIZATION, authorization);
+            }
+            let request = request.body(Body::empty()).unwrap();
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        // The users sharing the address aren't limited by its budget
+        assert_eq!(status(Some("right")).await, StatusCode::OK);
+        assert_eq!(status(Some("right")).await, StatusCode::OK);
+        // A single write is allowed without credentials or with wrong ones, then the address is
+        // refused until its budget refills
+        assert_eq!(status(Some("wrong")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(None).await, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(status(Some("right")).await, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[test]
+    fn evicts_the_buckets_full_again() {
+        let limiter = limiter();
+        let now = Instant::now();
+        limiter.take(user("alice"), Budget::Reads, now);
+        limiter.take(user("bob"), Budget::Writes, now);
+
+        assert_eq!(limiter.evict(now), 0);
+        // The read is refilled after a second, the write after 10
+        assert_eq!(limiter.evict(now + Duration::from_secs(1)), 1);
+        assert_eq!(limiter.evict(now + Duration::from_secs(5)), 0);
+        assert_eq!(limiter.evict(now + Duration::from_secs(10)), 1);
+        assert!(limiter.buckets.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,411 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{
+    DateTime, Datelike, Days, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    encode::IsNull,
+    error::BoxDynError,
+    sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef},
+    Decode, Encode, Sqlite, Type,
+};
+
+/// About 19 years for a weekly recurrence, keeps the dates far from overflowing
+static MAX_INTERVAL: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+/// A subset of the RFC 5545 recurrence rules, e.g. `FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH;COUNT=10`.
+/// `BYDAY` is only supported for weekly rules, and weeks start on monday.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct RecurrenceRule {
+    pub frequency: Frequency,
+    pub interval: u32,
+    /// Sorted from monday to sunday
+    pub by_day: Vec<Weekday>,
+    pub until: Option<DateTime<Utc>>,
+    pub count: Option<u32>,
+}
+
+impl RecurrenceRule {
+    /// Returns the date following `previous`, `previous` being the date of the `occurrence`th
+    /// occurrence (starting from 1), or `None` if the recurrence is over or the date would overflow
+    pub fn next_after(&self, previous: DateTime<Utc>, occurrence: u32) -> Option<DateTime<Utc>> {
+        if self.count.is_some_and(|count| occurrence >= count) {
+            return None;
+        }
+
+        let next = match self.frequency {
+            Frequency::Daily => previous.checked_add_days(Days::new(u64::from(self.interval)))?,
+            Frequency::Weekly if self.by_day.is_empty() => {
+                previous.checked_add_days(Days::new(u64::from(self.interval) * 7))?
+            }
+            Frequency::Weekly => self.next_weekly_by_day(previous)?,
+            Frequency::Monthly => self.next_monthly(previous)?,
+        };
+
+        match self.until {
+            Some(until) if next > until => None,
+            _ => Some(next),
+        }
+    }
+
+    /// The next day of `by_day` in the week of `previous`, otherwise the first one of the week
+    /// `interval` weeks later
+    fn next_weekly_by_day(&self, previous: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let previous_day = previous.weekday().num_days_from_monday();
+        if let Some(day) = self
+            .by_day
+            .iter()
+            .map(Weekday::num_days_from_monday)
+            .find(|day| *day > previous_day)
+        {
+            return previous.checked_add_days(Days::new(u64::from(day - previous_day)));
+        }
+
+        let first_day = self.by_day.first()?.num_days_from_monday();
+        let days = u64::from(self.interval) * 7 + u64::from(first_day) - u64::from(previous_day);
+        previous.checked_add_days(Days::new(days))
+    }
+
+    /// Months not having the day of `previous` (e.g. the 31st) are skipped, as in RFC 5545
+    fn next_monthly(&self, previous: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut months = self.interval;
+        // Any day of the month exists at least once every 12 months
+        while months <= self.interval.checked_mul(12)? {
+            let month = previous
+                .date_naive()
+                .with_day(1)?
+                .checked_add_months(Months::new(months))?;
+            if let Some(date) = month.with_day(previous.day()) {
+                return Some(Utc.from_utc_datetime(&date.and_time(previous.time())));
+            }
+            months = months.checked_add(self.interval)?;
+        }
+
+        None
+    }
+}
+
+impl fmt::Display for RecurrenceRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frequency = match self.frequency {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+        };
+        write!(f, "FREQ={frequency};INTERVAL={}", self.interval)?;
+        if !self.by_day.is_empty() {
+            let by_day = self
+                .by_day
+                .iter()
+                .map(|weekday| weekday_code(*weekday))
+                .collect::<Vec<_>>()
+                .join(",");
+            write!(f, ";BYDAY={by_day}")?;
+        }
+        if let Some(until) = self.until {
+            write!(f, ";UNTIL={}", until.format("%Y%m%dT%H%M%SZ"))?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={count}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for RecurrenceRule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let mut frequency = None;
+        let mut interval = 1;
+        let mut by_day = Vec::new();
+        let mut until = None;
+        let mut count = None;
+
+        for part in rule.split(';').filter(|part| !part.is_empty()) {
+            let Some((name, value)) = part.split_once('=') else {
+                return Err(format!("invalid recurrence rule part {part}"));
+            };
+            match name.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        _ => return Err(format!("unsupported recurrence frequency {value}")),
+                    });
+                }
+                "INTERVAL" => {
+                    interval = value
+                        .parse()
+                        .ok()
+                        .filter(|interval| (1..=MAX_INTERVAL).contains(interval))
+                        .ok_or_else(|| format!("invalid recurrence interval {value}"))?;
+                }
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        by_day.push(parse_weekday(day)?);
+                    }
+                }
+                "UNTIL" => until = Some(parse_until(value)?),
+                "COUNT" => {
+                    count = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|count| *count > 0)
+                            .ok_or_else(|| format!("invalid recurrence count {value}"))?,
+                    );
+                }
+                _ => return Err(format!("unsupported recurrence rule part {name}")),
+            }
+        }
+
+        let Some(frequency) = frequency else {
+            return Err("the recurrence frequency is missing".to_string());
+        };
+        if !by_day.is_empty() && frequency != Frequency::Weekly {
+            return Err("BYDAY is only supported for weekly recurrences".to_string());
+        }
+        if until.is_some() && count.is_some() {
+            return Err("UNTIL and COUNT can't be used together".to_string());
+        }
+        by_day.sort_by_key(Weekday::num_days_from_monday);
+        by_day.dedup();
+
+        Ok(Self {
+            frequency,
+            interval,
+            by_day,
+            until,
+            count,
+        })
+    }
+}
+
+impl TryFrom<String> for RecurrenceRule {
+    type Error = String;
+
+    fn try_from(rule: String) -> Result<Self, Self::Error> {
+        rule.parse()
+    }
+}
+
+impl From<RecurrenceRule> for String {
+    fn from(rule: RecurrenceRule) -> Self {
+        rule.to_string()
+    }
+}
+
+// Recurrence rules are stored as text
+impl Type<Sqlite> for RecurrenceRule {
+    fn type_info() -> SqliteTypeInfo {
+        <String as Type<Sqlite>>::type_info()
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for RecurrenceRule {
+    fn encode_by_ref(&self, buf: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+        <String as Encode<Sqlite>>::encode(self.to_string(), buf)
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for RecurrenceRule {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        Ok(<&str as Decode<Sqlite>>::decode(value)?.parse()?)
+    }
+}
+
+fn parse_weekday(day: &str) -> Result<Weekday, String> {
+    match day.to_ascii_uppercase().as_str() {
+        "MO" => Ok(Weekday::Mon),
+        "TU" => Ok(Weekday::Tue),
+        "WE" => Ok(Weekday::Wed),
+        "TH" => Ok(Weekday::Thu),
+        "FR" => Ok(Weekday::Fri),
+        "SA" => Ok(Weekday::Sat),
+        "SU" => Ok(Weekday::Sun),
+        _ => Err(format!("invalid recurrence day {day}")),
+    }
+}
+
+fn weekday_code(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+/// Accepts both the UTC date-time (`20261231T235959Z`) and the date (`20261231`) forms
+fn parse_until(until: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date_time) = NaiveDateTime::parse_from_str(until, "%Y%m%dT%H%M%SZ") {
+        return Ok(Utc.from_utc_datetime(&date_time));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(until, "%Y%m%d") {
+        return Ok(Utc.from_utc_datetime(&date.and_time(NaiveTime::MIN)));
+    }
+
+    Err(format!("invalid recurrence end {until}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(date: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(date).unwrap().to_utc()
+    }
+
+    fn rule(rule: &str) -> RecurrenceRule {
+        rule.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_and_formats_the_rules() {
+        let parsed = rule("freq=weekly;interval=2;byday=th,mo,TH;count=10");
+        assert_eq!(parsed.frequency, Frequency::Weekly);
+        assert_eq!(parsed.interval, 2);
+        assert_eq!(parsed.by_day, [Weekday::Mon, Weekday::Thu]);
+        assert_eq!(parsed.count, Some(10));
+        assert_eq!(
+            parsed.to_string(),
+            "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH;COUNT=10"
+        );
+
+        assert_eq!(rule("FREQ=DAILY").interval, 1);
+        assert_eq!(
+            rule("FREQ=MONTHLY;UNTIL=20261231").until,
+            Some(date("2026-12-31T00:00:00Z"))
+        );
+        assert_eq!(
+            rule("FREQ=MONTHLY;UNTIL=20261231T120000Z").to_string(),
+            "FREQ=MONTHLY;INTERVAL=1;UNTIL=20261231T120000Z"
+        );
+    }
+
+    #[test]
+    fn rejects_the_invalid_rules() {
+        for invalid in [
+            "",
+            "INTERVAL=2",
+            "FREQ=YEARLY",
+            "FREQ=DAILY;INTERVAL=0",
+            "FREQ=DAILY;INTERVAL=1001",
+            "FREQ=DAILY;INTERVAL=4294967295",
+            "FREQ=DAILY;INTERVAL=-1",
+            "FREQ=DAILY;COUNT=0",
+            "FREQ=DAILY;BYDAY=MO",
+            "FREQ=WEEKLY;BYDAY=XX",
+            "FREQ=DAILY;UNTIL=tomorrow",
+            "FREQ=DAILY;UNTIL=20261231;COUNT=2",
+            "FREQ=DAILY;BYMONTH=1",
+            "FREQ",
+        ] {
+            assert!(
+                invalid.parse::<RecurrenceRule>().is_err(),
+                "{invalid} should be rejected"
+            );
+        }
+        assert_eq!(rule("FREQ=DAILY;INTERVAL=1000").interval, 1000);
+    }
+
+    #[test]
+    fn recurs_daily_and_weekly() {
+        let previous = date("2026-10-19T09:30:00Z");
+        assert_eq!(
+            rule("FREQ=DAILY;INTERVAL=3").next_after(previous, 1),
+            Some(date("2026-10-22T09:30:00Z"))
+        );
+        assert_eq!(
+            rule("FREQ=WEEKLY;INTERVAL=2").next_after(previous, 1),
+            Some(date("2026-11-02T09:30:00Z"))
+        );
+    }
+
+    #[test]
+    fn recurs_on_the_days_of_the_week() {
+        let weekly = rule("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH");
+        // Monday, then thursday of the same week
+        let monday = date("2026-10-19T09:30:00Z");
+        let thursday = weekly.next_after(monday, 1);
+        assert_eq!(thursday, Some(date("2026-10-22T09:30:00Z")));
+        // Then monday two weeks later
+        assert_eq!(
+            weekly.next_after(thursday.unwrap(), 2),
+            Some(date("2026-11-02T09:30:00Z"))
+        );
+        // From a day not in the rule, sunday being the last day of the week
+        assert_eq!(
+            weekly.next_after(date("2026-10-25T09:30:00Z"), 1),
+            Some(date("2026-11-02T09:30:00Z"))
+        );
+        assert_eq!(
+            rule("FREQ=WEEKLY;BYDAY=WE").next_after(date("2026-10-21T09:30:00Z"), 1),
+            Some(date("2026-10-28T09:30:00Z"))
+        );
+    }
+
+    #[test]
+    fn skips_the_months_missing_the_day() {
+        let monthly = rule("FREQ=MONTHLY");
+        assert_eq!(
+            monthly.next_after(date("2026-01-31T08:00:00Z"), 1),
+            Some(date("2026-03-31T08:00:00Z"))
+        );
+        assert_eq!(
+            monthly.next_after(date("2026-03-31T08:00:00Z"), 2),
+            Some(date("2026-05-31T08:00:00Z"))
+        );
+        assert_eq!(
+            rule("FREQ=MONTHLY;INTERVAL=12").next_after(date("2024-02-29T08:00:00Z"), 1),
+            Some(date("2028-02-29T08:00:00Z"))
+        );
+        assert_eq!(
+            rule("FREQ=MONTHLY;INTERVAL=2").next_after(date("2026-10-15T08:00:00Z"), 1),
+            Some(date("2026-12-15T08:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn stops_after_the_count_or_the_end() {
+        let counted = rule("FREQ=DAILY;COUNT=3");
+        let previous = date("2026-10-19T09:30:00Z");
+        assert!(counted.next_after(previous, 2).is_some());
+        assert_eq!(counted.next_after(previous, 3), None);
+
+        let ended = rule("FREQ=DAILY;UNTIL=20261020T093000Z");
+        assert_eq!(
+            ended.next_after(previous, 1),
+            Some(date("2026-10-20T09:30:00Z"))
+        );
+        assert_eq!(ended.next_after(date("2026-10-20T09:30:00Z"), 2), None);
+    }
+
+    #[test]
+    fn stops_instead_of_overflowing() {
+        let last = DateTime::<Utc>::MAX_UTC - Days::new(1);
+        for rule in [
+            rule("FREQ=DAILY;INTERVAL=2"),
+            rule("FREQ=WEEKLY;INTERVAL=1000"),
+            rule("FREQ=WEEKLY;INTERVAL=1000;BYDAY=MO"),
+            rule("FREQ=MONTHLY;INTERVAL=1000"),
+        ] {
+            assert_eq!(rule.next_after(last, 1), None, "{rule}");
+        }
+    }
+}
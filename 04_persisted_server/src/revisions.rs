@@ -0,0 +1,59 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, SqliteConnection};
+use uuid::Uuid;
+
+use crate::errors::{Error, Result};
+
+/// A past state of a todo, a new revision is recorded on every change
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct TodoRevision {
+    pub revision: i64,
+    pub content: String,
+    pub completed: bool,
+    /// Not recorded by the revisions prior to the workflow states
+    pub state: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Records the current state of the todo as its latest revision.
+/// Must be called in the same transaction as the change itself.
+pub async fn record_revision(conn: &mut SqliteConnection, todo_id: &Uuid) -> Result<()> {
+    sqlx::query("INSERT INTO todo_revisions (todo_id, revision, content, completed, state, created_at) SELECT id, (SELECT COALESCE(MAX(revision), 0) + 1 FROM todo_revisions WHERE todo_id = todos.id), content, completed, state, ? FROM todos WHERE id = ?")
+        .bind(Utc::now())
+        .bind(todo_id)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn select_revisions(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+) -> Result<Vec<TodoRevision>> {
+    let revisions = sqlx::query_as(
+        "SELECT revision, content, completed, state, created_at FROM todo_revisions WHERE todo_id = ? ORDER BY revision",
+    )
+    .bind(todo_id)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(revisions)
+}
+
+pub async fn select_revision(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+    revision: i64,
+) -> Result<TodoRevision> {
+    let revision = sqlx::query_as(
+        "SELECT revision, content, completed, state, created_at FROM todo_revisions WHERE todo_id = ? AND revision = ?",
+    )
+    .bind(todo_id)
+    .bind(revision)
+    .fetch_optional(conn)
+    .await?;
+
+    revision.ok_or(Error::NotFound)
+}
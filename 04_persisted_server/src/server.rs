@@ -0,0 +1,187 @@
+use std::{future::IntoFuture, net::SocketAddr, pin::pin, sync::Arc, time::Duration};
+
+use anyhow::{bail, Result};
+use axum::{
+    extract::{ConnectInfo, Request},
+    Router,
+};
+use hyper::{body::Incoming, server::conn::http1, service::service_fn};
+use hyper_util::{
+    rt::{TokioIo, TokioTimer},
+    server::graceful::{GracefulShutdown, Watcher},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    task::JoinSet,
+    time::{sleep, timeout},
+};
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+use tower::ServiceExt;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    config::{ServerConfig, TlsConfig},
+    listener::{Connection, Listener},
+    tasks,
+    tls::{self, Certificate},
+};
+
+/// Accepting again right away would most likely fail the same way, such as when running out of
+/// file descriptors
+static ACCEPT_ERROR_DELAY: Duration = Duration::from_secs(1);
+
+/// The connections that don't complete their handshake in time are dropped
+static TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The connections that don't send the headers of a request in time are closed, which also closes
+/// the idle keep-alive connections instead of holding the drain open until its timeout
+static HEADER_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serves HTTPS when a certificate is configured, plain HTTP otherwise, until the shutdown, then
+/// drains the in-flight requests
+pub async fn serve(
+    app: Router,
+    config: &ServerConfig,
+    tls_config: &TlsConfig,
+    shutdown: &CancellationToken,
+) -> Result<()> {
+    let listener = Listener::bind(config).await?;
+    let shutdown_delay = Duration::from_secs(config.shutdown_delay_secs);
+    let drain_timeout = Duration::from_secs(config.shutdown_timeout_secs);
+
+    let (tls, certificate_reload) = match (&tls_config.cert_path, &tls_config.key_path) {
+        (Some(cert_path), Some(key_path)) => {
+            let certificate = Arc::new(Certificate::load(cert_path, key_path)?);
+            let certificate_reload =
+                tasks::spawn_certificate_reload(certificate.clone(), shutdown.clone());
+            (
+                Some(tls::tls_acceptor(certificate)?),
+                Some(certificate_reload),
+            )
+        }
+        _ => (None, None),
+    };
+    let redirect = match tls_config.redirect_bind {
+        Some(redirect_bind) => {
+            let Some(https_port) = listener.port() else {
+                bail!("the redirection to HTTPS requires a TCP listener");
+            };
+            let redirect_listener = TcpListener::bind(redirect_bind).await?;
+            let redirect = axum::serve(redirect_listener, tls::redirect_router(https_port))
+                .with_graceful_shutdown(shutdown.clone().cancelled_owned());
+            Some(tokio::spawn(redirect.into_future()))
+        }
+        None => None,
+    };
+    info!(
+        "listening on {}{}",
+        listener,
+        if tls.is_some() { " with TLS" } else { "" }
+    );
+
+    let graceful = GracefulShutdown::new();
+    // Kept to cut off the connections still in flight after the drain timeout
+    let mut connections = JoinSet::new();
+    // The connections are still accepted during the shutdown delay
+    let mut stop_accepting = pin!(async {
+        shutdown.cancelled().await;
+        sleep(shutdown_delay).await;
+    });
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            // Forgets the closed connections
+            Some(_) = connections.join_next() => continue,
+            () = &mut stop_accepting => break,
+        };
+        match accepted {
+            Ok(Connection::Tcp(stream, address)) => {
+                let connection = serve_connection(
+                    stream,
+                    Some(address),
+                    app.clone(),
+                    tls.clone(),
+                    graceful.watcher(),
+                );
+                connections.spawn(connection);
+            }
+            Ok(Connection::Unix(stream)) => {
+                let connection =
+                    serve_connection(stream, None, app.clone(), tls.clone(), graceful.watcher());
+                connections.spawn(connection);
+            }
+            Err(err) => {
+                error!("couldn't accept a connection: {err}");
+                sleep(ACCEPT_ERROR_DELAY).await;
+            }
+        }
+    }
+    // Also removes the Unix socket
+    drop(listener);
+
+    tokio::select! {
+        () = graceful.shutdown() => {}
+        () = sleep(drain_timeout) => {
+            warn!("the in-flight requests weren't drained in time, cutting them off");
+            connections.shutdown().await;
+        }
+    }
+    if let Some(certificate_reload) = certificate_reload {
+        tasks::join([("certificate reload", certificate_reload)]).await;
+    }
+    if let Some(redirect) = redirect {
+        redirect.await??;
+    }
+
+    Ok(())
+}
+
+/// The clients connected through a Unix socket have no address
+async fn serve_connection<S>(
+    stream: S,
+    address: Option<SocketAddr>,
+    app: Router,
+    tls: Option<TlsAcceptor>,
+    watcher: Watcher,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    match tls {
+        None => serve_http(stream, address, app, watcher).await,
+        Some(tls) => match timeout(TLS_HANDSHAKE_TIMEOUT, tls.accept(stream)).await {
+            Ok(Ok(stream)) => serve_http(stream, address, app, watcher).await,
+            Ok(Err(err)) => debug!("TLS handshake with {} failed: {err}", peer(address)),
+            Err(_) => debug!("TLS handshake with {} timed out", peer(address)),
+        },
+    }
+}
+
+async fn serve_http<S>(stream: S, address: Option<SocketAddr>, app: Router, watcher: Watcher)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = service_fn(move |mut request: Request<Incoming>| {
+        // The address of the client is needed by the rate limiter and the login guard
+        if let Some(address) = address {
+            request.extensions_mut().insert(ConnectInfo(address));
+        }
+        app.clone().oneshot(request)
+    });
+    let connection = http1::Builder::new()
+        .timer(TokioTimer::new())
+        .header_read_timeout(HEADER_READ_TIMEOUT)
+        .serve_connection(TokioIo::new(stream), service);
+
+    if let Err(err) = watcher.watch(connection).await {
+        debug!("connection with {} failed: {err}", peer(address));
+    }
+}
+
+fn peer(address: Option<SocketAddr>) -> String {
+    address.map_or_else(
+        || "a Unix socket client".to_string(),
+        |address| address.to_string(),
+    )
+}
@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, SqliteConnection};
+use uuid::Uuid;
+
+use crate::errors::{Error, Result};
+
+/// Identified by its name, like in the filters and the tags of the todos
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct Tag {
+    pub name: String,
+    /// Number of todos, not in the trash, having the tag
+    pub todos_count: i64,
+}
+
+pub async fn select_tags(conn: &mut SqliteConnection) -> Result<Vec<Tag>> {
+    let tags = sqlx::query_as("SELECT name, (SELECT COUNT(*) FROM todo_tags JOIN todos ON todos.id = todo_tags.todo_id WHERE todo_tags.tag_id = tags.id AND todos.deleted_at IS NULL) AS todos_count FROM tags ORDER BY name")
+        .fetch_all(conn)
+        .await?;
+
+    Ok(tags)
+}
+
+pub async fn create_tag(conn: &mut SqliteConnection, name: &str) -> Result<()> {
+    check_name(name)?;
+    sqlx::query("INSERT INTO tags (name) VALUES (?)")
+        .bind(name)
+        .execute(conn)
+        .await
+        .map_err(|err| conflict_on_unique_violation(err, name))?;
+
+    Ok(())
+}
+
+/// The todos having the tag, whether they are in the trash or not
+pub async fn select_tagged_todo_ids(conn: &mut SqliteConnection, name: &str) -> Result<Vec<Uuid>> {
+    let todo_ids = sqlx::query_scalar(
+        "SELECT todo_tags.todo_id FROM todo_tags JOIN tags ON tags.id = todo_tags.tag_id WHERE tags.name = ?",
+    )
+    .bind(name)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(todo_ids)
+}
+
+pub async fn rename_tag(conn: &mut SqliteConnection, name: &str, new_name: &str) -> Result<()> {
+    check_name(new_name)?;
+    let result = sqlx::query("UPDATE tags SET name = ? WHERE name = ?")
+        .bind(new_name)
+        .bind(name)
+        .execute(conn)
+        .await
+        .map_err(|err| conflict_on_unique_violation(err, new_name))?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound);
+    }
+
+    Ok(())
+}
+
+/// Removes the tag from all the todos too
+pub async fn remove_tag(conn: &mut SqliteConnection, name: &str) -> Result<()> {
+    let result = sqlx::query("DELETE FROM tags WHERE name = ?")
+        .bind(name)
+        .execute(conn)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound);
+    }
+
+    Ok(())
+}
+
+/// Tags the todo, the tag is created if it doesn't exist yet
+pub async fn add_todo_tag(conn: &mut SqliteConnection, todo_id: &Uuid, name: &str) -> Result<()> {
+    check_name(name)?;
+    let todo = sqlx::query("SELECT id FROM todos WHERE id = ? AND deleted_at IS NULL")
+        .bind(todo_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+    if todo.is_none() {
+        return Err(Error::NotFound);
+    }
+
+    sqlx::query("INSERT OR IGNORE INTO tags (name) VALUES (?)")
+        .bind(name)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("INSERT OR IGNORE INTO todo_tags SELECT ?, id FROM tags WHERE name = ?")
+        .bind(todo_id)
+        .bind(name)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn remove_todo_tag(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+    name: &str,
+) -> Result<()> {
+    let result = sqlx::query(
+        "DELETE FROM todo_tags WHERE todo_id = ? AND tag_id = (SELECT id FROM tags WHERE name = ?)",
+    )
+    .bind(todo_id)
+    .bind(name)
+    .execute(conn)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound);
+    }
+
+    Ok(())
+}
+
+/// Replaces all the tags of the todo
+pub async fn set_todo_tags(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+    names: &[String],
+) -> Result<()> {
+    sqlx::query("DELETE FROM todo_tags WHERE todo_id = ?")
+        .bind(todo_id)
+        .execute(&mut *conn)
+        .await?;
+
+    for name in names {
+        add_todo_tag(&mut *conn, todo_id, name).await?;
+    }
+
+    Ok(())
+}
+
+fn check_name(name: &str) -> Result<()> {
+    if name.trim().is_empty() {
+        return Err(Error::Invalid("a tag name can't be blank".to_string()));
+    }
+
+    Ok(())
+}
+
+fn conflict_on_unique_violation(err: sqlx::Error, name: &str) -> Error {
+    match err.as_database_error() {
+        Some(db_err) if db_err.is_unique_violation() => {
+            Error::Conflict(format!("the tag {name} already exists"))
+        }
+        _ => err.into(),
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use sqlx::{Pool, Sqlite};
+use tokio::{task::JoinHandle, time::interval};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info};
+
+use crate::{
+    attachments::{self, AttachmentStorage},
+    db,
+    errors::Result,
+    lockouts::LoginGuard,
+    notifications::{Notifier, ReminderEvent},
+    rate_limit::RateLimiter,
+    tls::Certificate,
+    todos,
+};
+
+static TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+static REMINDERS_INTERVAL: Duration = Duration::from_secs(30);
+
+static RATE_LIMIT_EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+static LOGIN_FAILURES_EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+static CERTIFICATE_RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Periodically and permanently deletes the todos that stayed in the trash longer than `retention`,
+/// along with their attachments, until the server shuts down
+pub fn spawn_trash_purge(
+    pool: Pool<Sqlite>,
+    retention: Duration,
+    storage: AttachmentStorage,
+    shutdown: CancellationToken,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = interval(TRASH_PURGE_INTERVAL);
+        loop {
+            // A purge in progress is completed
+            tokio::select! {
+                _ = interval.tick() => {}
+                () = shutdown.cancelled() => break,
+            }
+            if let Err(err) = purge_trash(&pool, retention, &storage).await {
+                error!("couldn't purge the trash: {err}");
+            }
+        }
+        debug!("trash purge stopped");
+    })
+}
+
+async fn purge_trash(
+    pool: &Pool<Sqlite>,
+    retention: Duration,
+    storage: &AttachmentStorage,
+) -> Result<()> {
+    // A retention reaching before the earliest representable date keeps the whole trash
+    let Some(deleted_before) = chrono::Duration::from_std(retention)
+        .ok()
+        .and_then(|retention| Utc::now().checked_sub_signed(retention))
+    else {
+        return Ok(());
+    };
+    let mut tx = db::begin_write(pool).await?;
+    let sha256s = attachments::select_trashed_sha256s(&mut tx).await?;
+    let purged = todos::purge_trash(&mut tx, deleted_before).await?;
+    tx.commit().await?;
+    debug!("purged {purged} todo(s) from the trash");
+
+    storage
+        .remove_unreferenced(&mut *pool.acquire().await?, &sha256s)
+        .await
+}
+
+/// Periodically fires the reminders that are due, until the server shuts down
+pub fn spawn_reminder_scheduler(
+    pool: Pool<Sqlite>,
+    notifier: Notifier,
+    shutdown: CancellationToken,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = interval(REMINDERS_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                () = shutdown.cancelled() => break,
+            }
+            if let Err(err) = fire_reminders(&pool, &notifier).await {
+                error!("couldn't fire the reminders: {err}");
+            }
+        }
+        debug!("reminder scheduler stopped");
+    })
+}
+
+async fn fire_reminders(pool: &Pool<Sqlite>, notifier: &Notifier) -> Result<()> {
+    let mut conn = pool.acquire().await?;
+    let todos = todos::take_due_reminders(&mut conn).await?;
+    // The connection is not needed while delivering the notifications
+    drop(conn);
+
+    for todo in todos {
+        let event = ReminderEvent {
+            todo,
+            fired_at: Utc::now(),
+        };
+        notifier.notify(&event).await;
+    }
+
+    Ok(())
+}
+
+/// Periodically forgets the clients whose budgets are full again, bounding the memory used by the
+/// rate limiter, until the server shuts down
+pub fn spawn_rate_limit_eviction(
+    limiter: RateLimiter,
+    shutdown: CancellationToken,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = interval(RATE_LIMIT_EVICTION_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                () = shutdown.cancelled() => break,
+            }
+            let evicted = limiter.evict(Instant::now());
+            debug!("evicted {evicted} rate limit bucket(s)");
+        }
+        debug!("rate limit eviction stopped");
+    })
+}
+
+/// Periodically forgets the failed authentications that are not relevant anymore, bounding the
+/// memory used by the login guard, until the server shuts down
+pub fn spawn_login_failures_eviction(
+    guard: LoginGuard,
+    shutdown: CancellationToken,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = interval(LOGIN_FAILURES_EVICTION_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                () = shutdown.cancelled() => break,
+            }
+            let evicted = guard.evict(Utc::now());
+            debug!("evicted {evicted} login failure(s)");
+        }
+        debug!("login failures eviction stopped");
+    })
+}
+
+/// Periodically reloads the TLS certificate when its files are modified, until the server shuts
+/// down. The new certificate is served to the new connections.
+pub fn spawn_certificate_reload(
+    certificate: Arc<Certificate>,
+    shutdown: CancellationToken,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = interval(CERTIFICATE_RELOAD_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                () = shutdown.cancelled() => break,
+            }
+            match certificate.reload_if_modified() {
+                Ok(true) => info!("reloaded the TLS certificate"),
+                Ok(false) => {}
+                Err(err) => error!("couldn't reload the TLS certificate: {err}"),
+            }
+        }
+        debug!("certificate reload stopped");
+    })
+}
+
+/// Waits for the tasks to stop, a task that panicked is logged so that the shutdown goes on
+pub async fn join<const N: usize>(tasks: [(&str, JoinHandle<()>); N]) {
+    for (name, task) in tasks {
+        if let Err(err) = task.await {
+            error!("the {name} task failed: {err}");
+        }
+    }
+}
@@ -0,0 +1,55 @@
+#[cfg(feature = "otel")]
+use opentelemetry_sdk::trace::SdkTracerProvider;
+#[cfg(feature = "otel")]
+use tracing::warn;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+use crate::config::{Config, LogFormat};
+#[cfg(feature = "otel")]
+use crate::otel;
+
+/// Dropping it flushes the spans not exported yet
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    tracer_provider: Option<SdkTracerProvider>,
+}
+
+/// Logs to the standard output and, with the `otel` feature, exports the spans to the configured
+/// OpenTelemetry collector, both filtered by the logging filter
+pub fn init_telemetry(config: &Config) -> anyhow::Result<Telemetry> {
+    let output = match config.logging.format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer().json().boxed(),
+    };
+    let subscriber = tracing_subscriber::registry()
+        .with(EnvFilter::new(&config.logging.filter))
+        .with(output);
+
+    #[cfg(feature = "otel")]
+    let tracer_provider = config
+        .otel
+        .endpoint
+        .as_deref()
+        .map(|endpoint| otel::tracer_provider(&config.otel, endpoint))
+        .transpose()?;
+    #[cfg(feature = "otel")]
+    let subscriber = subscriber.with(tracer_provider.as_ref().map(otel::layer));
+
+    subscriber.try_init()?;
+
+    Ok(Telemetry {
+        #[cfg(feature = "otel")]
+        tracer_provider,
+    })
+}
+
+#[cfg(feature = "otel")]
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(tracer_provider) = &self.tracer_provider {
+            if let Err(err) = tracer_provider.shutdown() {
+                warn!("can't export the remaining spans: {err}");
+            }
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, QueryBuilder, Sqlite, SqliteConnection};
+use uuid::Uuid;
+
+use crate::{
+    errors::{Error, Result},
+    todos,
+};
+
+/// The duration of an entry in seconds, up to now for a running timer
+static SECONDS: &str = "CAST(ROUND((julianday(COALESCE(stopped_at, 'now')) - julianday(started_at)) * 86400) AS INTEGER)";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct TimeEntry {
+    pub id: Uuid,
+    pub todo_id: Uuid,
+    pub username: String,
+    pub started_at: DateTime<Utc>,
+    /// Not set while the timer is running
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub seconds: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TodoTime {
+    pub total_seconds: i64,
+    pub entries: Vec<TimeEntry>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeReportFilter {
+    pub username: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct TodoTotal {
+    pub todo_id: Uuid,
+    pub content: String,
+    pub seconds: i64,
+}
+
+/// The entries are counted on the day they started
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct DayTotal {
+    pub day: NaiveDate,
+    pub seconds: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeReport {
+    pub total_seconds: i64,
+    pub todos: Vec<TodoTotal>,
+    pub days: Vec<DayTotal>,
+}
+
+/// A user can only have one running timer, across all the todos
+pub async fn start_timer(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+    username: &str,
+) -> Result<()> {
+    if !todos::is_live(&mut *conn, todo_id).await? {
+        return Err(Error::NotFound);
+    }
+
+    sqlx::query("INSERT INTO time_entries (id, todo_id, username, started_at) VALUES (?, ?, ?, ?)")
+        .bind(Uuid::new_v4())
+        .bind(todo_id)
+        .bind(username)
+        .bind(Utc::now())
+        .execute(conn)
+        .await
+        .map_err(|err| match err.as_database_error() {
+            Some(db_err) if db_err.is_unique_violation() => {
+                Error::Conflict(format!("{username} already has a running timer"))
+            }
+            _ => err.into(),
+        })?;
+
+    Ok(())
+}
+
+pub async fn stop_timer(conn: &mut SqliteConnection, todo_id: &Uuid, username: &str) -> Result<()> {
+    if !todos::is_live(&mut *conn, todo_id).await? {
+        return Err(Error::NotFound);
+    }
+
+    let result = sqlx::query("UPDATE time_entries SET stopped_at = ? WHERE todo_id = ? AND username = ? AND stopped_at IS NULL")
+        .bind(Utc::now())
+        .bind(todo_id)
+        .bind(username)
+        .execute(conn)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound);
+    }
+
+    Ok(())
+}
+
+/// Stops the running timers of all the users on the todo
+pub async fn stop_todo_timers(conn: &mut SqliteConnection, todo_id: &Uuid) -> Result<()> {
+    sqlx::query("UPDATE time_entries SET stopped_at = ? WHERE todo_id = ? AND stopped_at IS NULL")
+        .bind(Utc::now())
+        .bind(todo_id)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn create_time_entry(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+    username: &str,
+    started_at: DateTime<Utc>,
+    stopped_at: DateTime<Utc>,
+) -> Result<()> {
+    if stopped_at <= started_at {
+        return Err(Error::Invalid(
+            "a time entry must stop after it started".to_string(),
+        ));
+    }
+    if !todos::is_live(&mut *conn, todo_id).await? {
+        return Err(Error::NotFound);
+    }
+
+    sqlx::query("INSERT INTO time_entries (id, todo_id, username, started_at, stopped_at) VALUES (?, ?, ?, ?, ?)")
+        .bind(Uuid::new_v4())
+        .bind(todo_id)
+        .bind(username)
+        .bind(started_at)
+        .bind(stopped_at)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn select_todo_time(conn: &mut SqliteConnection, todo_id: &Uuid) -> Result<TodoTime> {
+    if !todos::is_live(&mut *conn, todo_id).await? {
+        return Err(Error::NotFound);
+    }
+
+    let query = format!("SELECT id, todo_id, username, started_at, stopped_at, {SECONDS} AS seconds FROM time_entries WHERE todo_id = ? ORDER BY julianday(started_at)");
+    let entries: Vec<TimeEntry> = sqlx::query_as(&query).bind(todo_id).fetch_all(conn).await?;
+
+    Ok(TodoTime {
+        total_seconds: entries.iter().map(|entry| entry.seconds).sum(),
+        entries,
+    })
+}
+
+/// Only the todos that are not in the trash are reported
+pub async fn select_time_report(
+    conn: &mut SqliteConnection,
+    filter: &TimeReportFilter,
+) -> Result<TimeReport> {
+    let mut query = QueryBuilder::<Sqlite>::new(format!(
+        "SELECT todos.id AS todo_id, todos.content, SUM({SECONDS}) AS seconds FROM time_entries JOIN todos ON todos.id = time_entries.todo_id"
+    ));
+    push_report_conditions(&mut query, filter);
+    query.push(" GROUP BY todos.id ORDER BY seconds DESC");
+    let todos: Vec<TodoTotal> = query.build_query_as().fetch_all(&mut *conn).await?;
+
+    let mut query = QueryBuilder::<Sqlite>::new(format!(
+        "SELECT date(started_at) AS day, SUM({SECONDS}) AS seconds FROM time_entries JOIN todos ON todos.id = time_entries.todo_id"
+    ));
+    push_report_conditions(&mut query, filter);
+    query.push(" GROUP BY day ORDER BY day");
+    let days = query.build_query_as().fetch_all(conn).await?;
+
+    Ok(TimeReport {
+        total_seconds: todos.iter().map(|todo| todo.seconds).sum(),
+        todos,
+        days,
+    })
+}
+
+fn push_report_conditions<'a>(query: &mut QueryBuilder<'a, Sqlite>, filter: &'a TimeReportFilter) {
+    query.push(" WHERE todos.deleted_at IS NULL");
+    if let Some(username) = &filter.username {
+        query.push(" AND username = ").push_bind(username);
+    }
+    if let Some(since) = filter.since {
+        query
+            .push(" AND julianday(started_at) >= julianday(")
+            .push_bind(since)
+            .push(")");
+    }
+    if let Some(until) = filter.until {
+        query
+            .push(" AND julianday(started_at) < julianday(")
+            .push_bind(until)
+            .push(")");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db::create_test_db_pool, payloads::NewTodoRequest, workflow::DEFAULT_WORKFLOW};
+
+    async fn create_todo(conn: &mut SqliteConnection, content: &str) -> Uuid {
+        let new_todo = NewTodoRequest {
+            content: content.to_string(),
+            ..Default::default()
+        };
+        todos::create_todo(conn, &new_todo, &DEFAULT_WORKFLOW.parse().unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn removing_a_todo_stops_its_timers() {
+        let pool = create_test_db_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        let removed_id = create_todo(&mut conn, "removed").await;
+        let other_id = create_todo(&mut conn, "other").await;
+        start_timer(&mut conn, &removed_id, "admin").await.unwrap();
+        let result = start_timer(&mut conn, &other_id, "admin").await;
+        assert!(matches!(result, Err(Error::Conflict(_))), "{result:?}");
+
+        todos::remove_todo(&mut conn, &removed_id).await.unwrap();
+        start_timer(&mut conn, &other_id, "admin").await.unwrap();
+
+        todos::restore_todo(&mut conn, &removed_id).await.unwrap();
+        let time = select_todo_time(&mut conn, &removed_id).await.unwrap();
+        assert_eq!(time.entries.len(), 1);
+        assert!(time.entries[0].stopped_at.is_some());
+    }
+}
@@ -1,73 +1,958 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{pool::PoolConnection, prelude::FromRow, Sqlite};
+use std::collections::{HashMap, HashSet};
+
+use sqlx::{prelude::FromRow, types::Json, QueryBuilder, Sqlite, SqliteConnection};
+use tracing::instrument;
 use uuid::Uuid;
 
-use crate::errors::Result;
+use crate::{
+    errors::{Error, Result},
+    payloads::{MoveTodoRequest, NewTodoRequest, TodosFilterRequest, UpdateTodoRequest},
+    positions, projects,
+    recurrence::RecurrenceRule,
+    revisions, tags, time_tracking,
+    workflow::Workflow,
+};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// The columns matching the `Todo` fields
+static TODO_COLUMNS: &str = concat!(
+    "id, content, completed, state, position, due_at, remind_at, priority, parent_id, project_id, recurrence, ",
+    "(SELECT json_group_array(name) FROM (SELECT tags.name FROM todo_tags JOIN tags ON tags.id = todo_tags.tag_id WHERE todo_tags.todo_id = todos.id ORDER BY tags.name)) AS tags, ",
+    "(SELECT json_object('completed', SUM(children.completed), 'total', COUNT(*)) FROM todos AS children WHERE children.parent_id = todos.id AND children.deleted_at IS NULL HAVING COUNT(*) > 0) AS progress, ",
+    "EXISTS (SELECT 1 FROM todo_dependencies JOIN todos AS blockers ON blockers.id = todo_dependencies.blocker_id WHERE todo_dependencies.todo_id = todos.id AND blockers.completed = 0 AND blockers.deleted_at IS NULL) AS blocked, ",
+    "(SELECT COUNT(*) FROM comments WHERE comments.todo_id = todos.id) AS comments_count",
+);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[allow(clippy::module_name_repetitions)]
 pub enum TodosFilter {
+    #[default]
     #[serde(rename = "all")]
     All,
+    /// In the terminal state of the workflow
     #[serde(rename = "completed")]
     Completed,
     #[serde(rename = "not-completed")]
     NotCompleted,
+    /// Not completed and past their due date
+    #[serde(rename = "overdue")]
+    Overdue,
+    /// Blocked by at least one todo not completed yet
+    #[serde(rename = "blocked")]
+    Blocked,
+    /// Not completed and not blocked
+    #[serde(rename = "actionable")]
+    Actionable,
+}
+
+impl TodosFilter {
+    fn condition(self) -> &'static str {
+        match self {
+            TodosFilter::All => "1 = 1",
+            TodosFilter::Completed => "completed = 1",
+            TodosFilter::NotCompleted => "completed = 0",
+            TodosFilter::Overdue => "completed = 0 AND julianday(due_at) < julianday('now')",
+            // SQLite lets the conditions refer to the `blocked` computed column
+            TodosFilter::Blocked => "blocked = 1",
+            TodosFilter::Actionable => "completed = 0 AND blocked = 0",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(clippy::module_name_repetitions)]
+pub enum TodosSort {
+    /// Most urgent first, todos without priority last
+    #[serde(rename = "priority")]
+    Priority,
+    /// Soonest due first, todos without due date last
+    #[serde(rename = "due")]
+    Due,
+}
+
+impl TodosSort {
+    fn order_by(self) -> &'static str {
+        match self {
+            TodosSort::Priority => "priority IS NULL, priority DESC",
+            TodosSort::Due => "due_at IS NULL, julianday(due_at)",
+        }
+    }
 }
 
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+#[repr(i64)]
+pub enum Priority {
+    #[serde(rename = "low")]
+    Low = 0,
+    #[serde(rename = "normal")]
+    Normal = 1,
+    #[serde(rename = "high")]
+    High = 2,
+    #[serde(rename = "urgent")]
+    Urgent = 3,
+}
+
+/// The fields added after the audit log are defaulted, the snapshots it recorded before lack them
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
 pub struct Todo {
     pub id: Uuid,
     pub content: String,
+    /// Whether the todo is in the terminal state
     pub completed: bool,
+    /// One of the states of the workflow
+    #[serde(default)]
+    pub state: String,
+    /// The todos are listed by position, unless sorted otherwise
+    #[serde(default)]
+    pub position: String,
+    pub due_at: Option<DateTime<Utc>>,
+    pub remind_at: Option<DateTime<Utc>>,
+    pub priority: Option<Priority>,
+    pub parent_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
+    pub recurrence: Option<RecurrenceRule>,
+    #[serde(default)]
+    #[sqlx(json)]
+    pub tags: Vec<String>,
+    /// Only set for todos having children
+    pub progress: Option<Json<Progress>>,
+    /// Whether a todo blocking this one is not completed yet
+    #[serde(default)]
+    pub blocked: bool,
+    #[serde(default)]
+    pub comments_count: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Progress {
+    pub completed: u32,
+    pub total: u32,
+}
+
+/// A todo along with its children, recursively
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TodoNode {
+    #[serde(flatten)]
+    pub todo: Todo,
+    pub children: Vec<TodoNode>,
+}
+
+/// What happens to the children of a removed todo
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChildrenRemoval {
+    /// The children become top-level todos
+    #[default]
+    #[serde(rename = "orphan")]
+    Orphan,
+    /// The children are removed too, recursively
+    #[serde(rename = "cascade")]
+    Cascade,
+}
+
+/// A todo that has been moved to the trash, it can be restored until it gets purged
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct TrashedTodo {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub todo: Todo,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// A todo as stored, whether it is in the trash or not
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct TodoRecord {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub todo: Todo,
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 #[allow(clippy::module_name_repetitions)]
+#[instrument(level = "debug", skip_all)]
 pub async fn select_todos(
-    mut conn: PoolConnection<Sqlite>,
-    filter: TodosFilter,
+    conn: &mut SqliteConnection,
+    filters: &TodosFilterRequest,
 ) -> Result<Vec<Todo>> {
-    let query = match filter {
-        TodosFilter::All => "SELECT id, content, completed FROM todos",
-        TodosFilter::Completed => "SELECT id, content, completed FROM todos WHERE completed = 1",
-        TodosFilter::NotCompleted => "SELECT id, content, completed FROM todos WHERE completed = 0",
-    };
+    let mut query = QueryBuilder::<Sqlite>::new(format!(
+        "SELECT {TODO_COLUMNS} FROM todos WHERE deleted_at IS NULL AND {}",
+        filters.filter.condition()
+    ));
+    if let Some(due_before) = &filters.due_before {
+        query
+            .push(" AND julianday(due_at) < julianday(")
+            .push_bind(due_before)
+            .push(")");
+    }
+    if let Some(due_after) = &filters.due_after {
+        query
+            .push(" AND julianday(due_at) > julianday(")
+            .push_bind(due_after)
+            .push(")");
+    }
+    if let Some(state) = &filters.state {
+        query.push(" AND state = ").push_bind(state);
+    }
+    if let Some(priority) = filters.priority {
+        query.push(" AND priority = ").push_bind(priority);
+    }
+    if let Some(project_id) = filters.project {
+        query.push(" AND project_id = ").push_bind(project_id);
+    }
+    if let Some(tag) = &filters.tag {
+        query
+            .push(" AND id IN (SELECT todo_tags.todo_id FROM todo_tags JOIN tags ON tags.id = todo_tags.tag_id WHERE tags.name = ")
+            .push_bind(tag)
+            .push(")");
+    }
+    query.push(" ORDER BY ");
+    if let Some(sort) = filters.sort {
+        query.push(sort.order_by()).push(", ");
+    }
+    query.push("position");
 
-    let todos = sqlx::query_as(query).fetch_all(conn.as_mut()).await?;
+    let todos = query.build_query_as().fetch_all(conn).await?;
 
     Ok(todos)
 }
 
-pub async fn create_todo(mut conn: PoolConnection<Sqlite>, content: &str) -> Result<()> {
-    sqlx::query("INSERT INTO todos VALUES (?, ?, ?)")
-        .bind(Uuid::new_v4())
-        .bind(content)
-        .bind(false)
-        .execute(conn.as_mut())
+/// Selects the todos, not in the trash, matching the condition bound to `id`
+#[instrument(level = "debug", skip_all)]
+pub async fn select_todos_where(
+    conn: &mut SqliteConnection,
+    condition: &str,
+    id: &Uuid,
+) -> Result<Vec<Todo>> {
+    let query = format!(
+        "SELECT {TODO_COLUMNS} FROM todos WHERE deleted_at IS NULL AND {condition} ORDER BY position"
+    );
+
+    let todos = sqlx::query_as(&query).bind(id).fetch_all(conn).await?;
+
+    Ok(todos)
+}
+
+/// Whether the todo exists and is not in the trash
+#[instrument(level = "debug", skip_all, fields(todo_id = %todo_id))]
+pub async fn is_live(conn: &mut SqliteConnection, todo_id: &Uuid) -> Result<bool> {
+    let todo = sqlx::query("SELECT id FROM todos WHERE id = ? AND deleted_at IS NULL")
+        .bind(todo_id)
+        .fetch_optional(conn)
         .await?;
 
-    Ok(())
+    Ok(todo.is_some())
 }
 
+#[instrument(level = "debug", skip_all, fields(todo_id = %todo_id))]
+pub async fn select_todo_record(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+) -> Result<Option<TodoRecord>> {
+    let query = format!("SELECT {TODO_COLUMNS}, deleted_at FROM todos WHERE id = ?");
+
+    let todo = sqlx::query_as(&query)
+        .bind(todo_id)
+        .fetch_optional(conn)
+        .await?;
+
+    Ok(todo)
+}
+
+#[instrument(level = "debug", skip_all)]
+pub async fn create_todo(
+    conn: &mut SqliteConnection,
+    new_todo: &NewTodoRequest,
+    workflow: &Workflow,
+) -> Result<Uuid> {
+    if let Some(project_id) = &new_todo.project_id {
+        projects::check_project(&mut *conn, project_id).await?;
+    }
+
+    insert_todo(conn, new_todo, workflow).await
+}
+
+/// Creates the todo, whether its project is archived or not
+async fn insert_todo(
+    conn: &mut SqliteConnection,
+    new_todo: &NewTodoRequest,
+    workflow: &Workflow,
+) -> Result<Uuid> {
+    let todo_id = Uuid::new_v4();
+
+    if let Some(parent_id) = &new_todo.parent_id {
+        check_parent(&mut *conn, &todo_id, parent_id).await?;
+    }
+
+    // New todos go at the end of the list
+    let last_position: Option<String> = sqlx::query_scalar("SELECT MAX(position) FROM todos")
+        .fetch_one(&mut *conn)
+        .await?;
+    let position = positions::key_between(last_position.as_deref(), None);
+
+    sqlx::query("INSERT INTO todos (id, content, completed, state, position, due_at, remind_at, priority, parent_id, project_id, recurrence) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        .bind(todo_id)
+        .bind(&new_todo.content)
+        .bind(false)
+        .bind(workflow.initial())
+        .bind(position)
+        .bind(new_todo.due_at)
+        .bind(new_todo.remind_at)
+        .bind(new_todo.priority)
+        .bind(new_todo.parent_id)
+        .bind(new_todo.project_id)
+        .bind(&new_todo.recurrence)
+        .execute(&mut *conn)
+        .await
+        .map_err(conflict_on_position_violation)?;
+    tags::set_todo_tags(&mut *conn, &todo_id, &new_todo.tags).await?;
+    revisions::record_revision(conn, &todo_id).await?;
+
+    Ok(todo_id)
+}
+
+/// A blocked todo can't be completed unless forced, see `update_todo` for the returned id
+#[instrument(level = "debug", skip_all, fields(todo_id = %todo_id))]
 pub async fn set_todo_completion(
-    mut conn: PoolConnection<Sqlite>,
+    conn: &mut SqliteConnection,
     todo_id: &Uuid,
     completed: bool,
+    force: bool,
+    workflow: &Workflow,
+) -> Result<Option<Uuid>> {
+    let changes = UpdateTodoRequest {
+        completed: Some(completed),
+        force,
+        ..Default::default()
+    };
+
+    update_todo(conn, todo_id, &changes, workflow).await
+}
+
+/// Updates the provided fields only, and records the new state as a revision
+/// when the content or the completion changed.
+/// The state follows the transitions of the workflow, while completing a todo moves it straight
+/// to the terminal state, and reopening it moves it back to the initial state.
+/// Completing a recurring todo creates its next occurrence, whose id is returned.
+#[instrument(level = "debug", skip_all, fields(todo_id = %todo_id))]
+pub async fn update_todo(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+    changes: &UpdateTodoRequest,
+    workflow: &Workflow,
+) -> Result<Option<Uuid>> {
+    let Some(current_state) = sqlx::query_scalar::<_, String>(
+        "SELECT state FROM todos WHERE id = ? AND deleted_at IS NULL",
+    )
+    .bind(todo_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Err(Error::NotFound);
+    };
+    let state = match (changes.completed, &changes.state) {
+        (Some(_), Some(_)) => {
+            return Err(Error::Invalid(
+                "the completion and the state can't be changed together".to_string(),
+            ))
+        }
+        (Some(true), None) => Some(workflow.terminal.as_str()),
+        (Some(false), None) if workflow.is_terminal(&current_state) => Some(workflow.initial()),
+        (Some(false) | None, None) => None,
+        (None, Some(state)) => {
+            if !workflow.contains(state) {
+                return Err(Error::Invalid(format!(
+                    "{state} is not a state of the workflow"
+                )));
+            }
+            if !changes.reverting && !workflow.allows(&current_state, state) {
+                return Err(Error::Conflict(format!(
+                    "{todo_id} can't go from {current_state} to {state}"
+                )));
+            }
+            Some(state.as_str())
+        }
+    };
+
+    let completing = !workflow.is_terminal(&current_state)
+        && state.is_some_and(|state| workflow.is_terminal(state));
+    if completing && !changes.force && is_blocked(&mut *conn, todo_id).await? {
+        return Err(Error::Conflict(format!(
+            "{todo_id} is blocked by todos not completed yet"
+        )));
+    }
+
+    // `id = id` is a no-op that lets every change be prefixed with a comma
+    let mut query = QueryBuilder::<Sqlite>::new("UPDATE todos SET id = id");
+    if let Some(content) = &changes.content {
+        query.push(", content = ").push_bind(content);
+    }
+    if let Some(state) = state {
+        query
+            .push(", state = ")
+            .push_bind(state)
+            .push(", completed = ")
+            .push_bind(workflow.is_terminal(state));
+    }
+    if let Some(due_at) = changes.due_at {
+        query.push(", due_at = ").push_bind(due_at);
+    }
+    if let Some(remind_at) = changes.remind_at {
+        // A new reminder has to fire again
+        query
+            .push(", remind_at = ")
+            .push_bind(remind_at)
+            .push(", reminded_at = NULL");
+    }
+    if let Some(priority) = changes.priority {
+        query.push(", priority = ").push_bind(priority);
+    }
+    if let Some(parent_id) = changes.parent_id {
+        if let Some(parent_id) = &parent_id {
+            check_parent(&mut *conn, todo_id, parent_id).await?;
+        }
+        query.push(", parent_id = ").push_bind(parent_id);
+    }
+    if let Some(project_id) = changes.project_id {
+        if let Some(project_id) = &project_id {
+            projects::check_project(&mut *conn, project_id).await?;
+        }
+        query.push(", project_id = ").push_bind(project_id);
+    }
+    if let Some(recurrence) = &changes.recurrence {
+        query
+            .push(", recurrence = ")
+            .push_bind(recurrence)
+            .push(", occurrence = 1");
+    }
+    query
+        .push(" WHERE deleted_at IS NULL AND id = ")
+        .push_bind(todo_id);
+
+    let result = query.build().execute(&mut *conn).await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound);
+    }
+
+    if let Some(tags) = &changes.tags {
+        tags::set_todo_tags(&mut *conn, todo_id, tags).await?;
+    }
+
+    if changes.content.is_some() || state.is_some() {
+        revisions::record_revision(&mut *conn, todo_id).await?;
+    }
+
+    if completing {
+        return create_next_occurrence(conn, todo_id, workflow).await;
+    }
+
+    Ok(None)
+}
+
+/// Creates the todo following a recurring todo that has just been completed, and hands the
+/// recurrence over to it so that completing the todo again doesn't create another one
+#[instrument(level = "debug", skip_all, fields(todo_id = %todo_id))]
+async fn create_next_occurrence(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+    workflow: &Workflow,
+) -> Result<Option<Uuid>> {
+    let Some(TodoRecord { todo, .. }) = select_todo_record(&mut *conn, todo_id).await? else {
+        return Ok(None);
+    };
+    let Some(recurrence) = todo.recurrence else {
+        return Ok(None);
+    };
+    let occurrence: u32 = sqlx::query_scalar("SELECT occurrence FROM todos WHERE id = ?")
+        .bind(todo_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    // Todos without due date recur from their completion
+    let previous = todo.due_at.unwrap_or_else(Utc::now);
+    let Some(due_at) = recurrence.next_after(previous, occurrence) else {
+        return Ok(None);
+    };
+    let parent_id = match todo.parent_id {
+        Some(parent_id) if is_live(&mut *conn, &parent_id).await? => Some(parent_id),
+        _ => None,
+    };
+    let next_todo = NewTodoRequest {
+        content: todo.content,
+        due_at: Some(due_at),
+        remind_at: todo
+            .remind_at
+            .map(|remind_at| remind_at + (due_at - previous)),
+        priority: todo.priority,
+        tags: todo.tags,
+        parent_id,
+        project_id: todo.project_id,
+        recurrence: Some(recurrence),
+    };
+    // The occurrences stay in the project of the todo, even once the project is archived
+    let next_id = insert_todo(&mut *conn, &next_todo, workflow).await?;
+
+    sqlx::query("UPDATE todos SET occurrence = ? WHERE id = ?")
+        .bind(occurrence + 1)
+        .bind(next_id)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("UPDATE todos SET recurrence = NULL WHERE id = ?")
+        .bind(todo_id)
+        .execute(conn)
+        .await?;
+
+    Ok(Some(next_id))
+}
+
+/// Only the position of the moved todo changes, the todos in the trash keep their position
+/// so that they are restored where they were
+#[instrument(level = "debug", skip_all, fields(todo_id = %todo_id))]
+pub async fn move_todo(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+    todo_move: &MoveTodoRequest,
 ) -> Result<()> {
-    sqlx::query("UPDATE todos SET completed = ? WHERE id = ?")
-        .bind(completed)
+    if !is_live(&mut *conn, todo_id).await? {
+        return Err(Error::NotFound);
+    }
+
+    let (after, before) = match (todo_move.after, todo_move.before) {
+        (None, None) => {
+            return Err(Error::Invalid(
+                "the todo to move after or before is missing".to_string(),
+            ))
+        }
+        (Some(after_id), None) => {
+            let after = select_position(&mut *conn, todo_id, &after_id).await?;
+            let before = sqlx::query_scalar(
+                "SELECT MIN(position) FROM todos WHERE position > ? AND id != ?",
+            )
+            .bind(&after)
+            .bind(todo_id)
+            .fetch_one(&mut *conn)
+            .await?;
+            (Some(after), before)
+        }
+        (None, Some(before_id)) => {
+            let before = select_position(&mut *conn, todo_id, &before_id).await?;
+            let after = sqlx::query_scalar(
+                "SELECT MAX(position) FROM todos WHERE position < ? AND id != ?",
+            )
+            .bind(&before)
+            .bind(todo_id)
+            .fetch_one(&mut *conn)
+            .await?;
+            (after, Some(before))
+        }
+        (Some(after_id), Some(before_id)) => {
+            let after = select_position(&mut *conn, todo_id, &after_id).await?;
+            let before = select_position(&mut *conn, todo_id, &before_id).await?;
+            let between: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM todos WHERE position > ? AND position < ? AND id != ? AND deleted_at IS NULL")
+                .bind(&after)
+                .bind(&before)
+                .bind(todo_id)
+                .fetch_one(&mut *conn)
+                .await?;
+            if between > 0 {
+                return Err(Error::Invalid(
+                    "the todos to move between must be next to each other".to_string(),
+                ));
+            }
+            // The todos in the trash between them keep their position too
+            let next: Option<String> = sqlx::query_scalar(
+                "SELECT MIN(position) FROM todos WHERE position > ? AND position < ? AND id != ?",
+            )
+            .bind(&after)
+            .bind(&before)
+            .bind(todo_id)
+            .fetch_one(&mut *conn)
+            .await?;
+            (Some(after), Some(next.unwrap_or(before)))
+        }
+    };
+    let Some(position) = positions::key_between(after.as_deref(), before.as_deref()) else {
+        return Err(Error::Invalid(
+            "the todo to move after must come before the todo to move before".to_string(),
+        ));
+    };
+
+    sqlx::query("UPDATE todos SET position = ? WHERE id = ?")
+        .bind(position)
+        .bind(todo_id)
+        .execute(conn)
+        .await
+        .map_err(conflict_on_position_violation)?;
+
+    Ok(())
+}
+
+/// The positions are unique, a concurrent request can take the position computed for the todo
+fn conflict_on_position_violation(err: sqlx::Error) -> Error {
+    match err.as_database_error() {
+        Some(db_err) if db_err.is_unique_violation() => {
+            Error::Conflict("another todo took the position, try again".to_string())
+        }
+        _ => err.into(),
+    }
+}
+
+/// Returns the position of the todo next to which `todo_id` is moved
+#[instrument(level = "debug", skip_all, fields(todo_id = %todo_id))]
+async fn select_position(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+    neighbour_id: &Uuid,
+) -> Result<String> {
+    let position = sqlx::query_scalar(
+        "SELECT position FROM todos WHERE id = ? AND id != ? AND deleted_at IS NULL",
+    )
+    .bind(neighbour_id)
+    .bind(todo_id)
+    .fetch_optional(conn)
+    .await?;
+
+    position
+        .ok_or_else(|| Error::Invalid(format!("{neighbour_id} doesn't exist or is the moved todo")))
+}
+
+/// Moves the todo to the trash
+#[instrument(level = "debug", skip_all, fields(todo_id = %todo_id))]
+pub async fn remove_todo(conn: &mut SqliteConnection, todo_id: &Uuid) -> Result<()> {
+    let result = sqlx::query("UPDATE todos SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL")
+        .bind(Utc::now())
         .bind(todo_id)
-        .execute(conn.as_mut())
+        .execute(&mut *conn)
         .await?;
 
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound);
+    }
+
+    // The timers of a todo in the trash can't be stopped, and would keep their users from
+    // starting others
+    time_tracking::stop_todo_timers(conn, todo_id).await
+}
+
+#[instrument(level = "debug", skip_all)]
+pub async fn select_trashed_todos(conn: &mut SqliteConnection) -> Result<Vec<TrashedTodo>> {
+    let query = format!(
+        "SELECT {TODO_COLUMNS}, deleted_at FROM todos WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC"
+    );
+
+    let todos = sqlx::query_as(&query).fetch_all(conn).await?;
+
+    Ok(todos)
+}
+
+#[instrument(level = "debug", skip_all, fields(todo_id = %todo_id))]
+pub async fn restore_todo(conn: &mut SqliteConnection, todo_id: &Uuid) -> Result<()> {
+    let result =
+        sqlx::query("UPDATE todos SET deleted_at = NULL WHERE id = ? AND deleted_at IS NOT NULL")
+            .bind(todo_id)
+            .execute(conn)
+            .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound);
+    }
+
     Ok(())
 }
 
-pub async fn remove_todo(mut conn: PoolConnection<Sqlite>, todo_id: &Uuid) -> Result<()> {
-    sqlx::query("DELETE FROM todos WHERE id = ?")
+/// Permanently deletes a todo, only todos in the trash can be purged
+#[instrument(level = "debug", skip_all, fields(todo_id = %todo_id))]
+pub async fn purge_todo(conn: &mut SqliteConnection, todo_id: &Uuid) -> Result<()> {
+    let result = sqlx::query("DELETE FROM todos WHERE id = ? AND deleted_at IS NOT NULL")
         .bind(todo_id)
-        .execute(conn.as_mut())
+        .execute(conn)
         .await?;
 
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound);
+    }
+
     Ok(())
 }
+
+/// Permanently deletes the todos that were moved to the trash before `deleted_before`,
+/// returns the number of purged todos
+#[instrument(level = "debug", skip_all)]
+pub async fn purge_trash(
+    conn: &mut SqliteConnection,
+    deleted_before: DateTime<Utc>,
+) -> Result<u64> {
+    let result = sqlx::query(
+        "DELETE FROM todos WHERE deleted_at IS NOT NULL AND julianday(deleted_at) < julianday(?)",
+    )
+    .bind(deleted_before)
+    .execute(conn)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Marks the reminders that are due as sent, and returns their todos
+#[instrument(level = "debug", skip_all)]
+pub async fn take_due_reminders(conn: &mut SqliteConnection) -> Result<Vec<Todo>> {
+    let query = format!(
+        "UPDATE todos SET reminded_at = ? WHERE remind_at IS NOT NULL AND reminded_at IS NULL AND completed = 0 AND deleted_at IS NULL AND julianday(remind_at) <= julianday('now') RETURNING {TODO_COLUMNS}"
+    );
+
+    let todos = sqlx::query_as(&query)
+        .bind(Utc::now())
+        .fetch_all(conn)
+        .await?;
+
+    Ok(todos)
+}
+
+/// The parent must exist and not be in the trash, and mustn't be a descendant of the todo
+#[instrument(level = "debug", skip_all, fields(todo_id = %todo_id))]
+async fn check_parent(conn: &mut SqliteConnection, todo_id: &Uuid, parent_id: &Uuid) -> Result<()> {
+    if !is_live(&mut *conn, parent_id).await? {
+        return Err(Error::Invalid(format!(
+            "the parent {parent_id} doesn't exist"
+        )));
+    }
+
+    // Walks up the ancestors of the new parent, meeting the todo means there would be a cycle
+    let cycle = sqlx::query("WITH RECURSIVE ancestors(id) AS (SELECT ? UNION SELECT todos.parent_id FROM todos JOIN ancestors ON todos.id = ancestors.id WHERE todos.parent_id IS NOT NULL) SELECT id FROM ancestors WHERE id = ?")
+        .bind(parent_id)
+        .bind(todo_id)
+        .fetch_optional(conn)
+        .await?;
+    if cycle.is_some() {
+        return Err(Error::Conflict(format!(
+            "{parent_id} can't be the parent of {todo_id}, it would create a cycle"
+        )));
+    }
+
+    Ok(())
+}
+
+#[instrument(level = "debug", skip_all, fields(todo_id = %todo_id))]
+pub async fn select_children(conn: &mut SqliteConnection, todo_id: &Uuid) -> Result<Vec<Todo>> {
+    if !is_live(&mut *conn, todo_id).await? {
+        return Err(Error::NotFound);
+    }
+
+    select_todos_where(conn, "parent_id = ?", todo_id).await
+}
+
+/// Returns the ids of the children, grandchildren, etc. of the todo that are not in the trash
+#[instrument(level = "debug", skip_all, fields(todo_id = %todo_id))]
+pub async fn select_descendant_ids(
+    conn: &mut SqliteConnection,
+    todo_id: &Uuid,
+) -> Result<Vec<Uuid>> {
+    let ids = sqlx::query_scalar("WITH RECURSIVE descendants(id) AS (SELECT id FROM todos WHERE parent_id = ? AND deleted_at IS NULL UNION SELECT todos.id FROM todos JOIN descendants ON todos.parent_id = descendants.id WHERE todos.deleted_at IS NULL) SELECT id FROM descendants")
+        .bind(todo_id)
+        .fetch_all(conn)
+        .await?;
+
+    Ok(ids)
+}
+
+/// Nests the todos under their parent, the todos whose parent is not part of `todos` are roots.
+/// The order of the todos is preserved among siblings.
+pub fn build_tree(todos: Vec<Todo>) -> Vec<TodoNode> {
+    fn build_nodes(todos: Vec<Todo>, children: &mut HashMap<Uuid, Vec<Todo>>) -> Vec<TodoNode> {
+        todos
+            .into_iter()
+            .map(|todo| {
+                let todo_children = children.remove(&todo.id).unwrap_or_default();
+                TodoNode {
+                    children: build_nodes(todo_children, children),
+                    todo,
+                }
+            })
+            .collect()
+    }
+
+    let ids = todos.iter().map(|todo| todo.id).collect::<HashSet<_>>();
+    let mut roots = Vec::new();
+    let mut children = HashMap::<Uuid, Vec<Todo>>::new();
+    for todo in todos {
+        match todo.parent_id {
+            Some(parent_id) if ids.contains(&parent_id) => {
+                children.entry(parent_id).or_default().push(todo);
+            }
+            _ => roots.push(todo),
+        }
+    }
+
+    build_nodes(roots, &mut children)
+}
+
+#[instrument(level = "debug", skip_all, fields(todo_id = %todo_id))]
+async fn is_blocked(conn: &mut SqliteConnection, todo_id: &Uuid) -> Result<bool> {
+    let blocked = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM todo_dependencies JOIN todos AS blockers ON blockers.id = todo_dependencies.blocker_id WHERE todo_dependencies.todo_id = ? AND blockers.completed = 0 AND blockers.deleted_at IS NULL)")
+        .bind(todo_id)
+        .fetch_one(conn)
+        .await?;
+
+    Ok(blocked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db::create_test_db_pool, dependencies, workflow::DEFAULT_WORKFLOW};
+
+    fn new_todo(content: &str) -> NewTodoRequest {
+        NewTodoRequest {
+            content: content.to_string(),
+            ..Default::default()
+        }
+    }
+
+    async fn create_todos(conn: &mut SqliteConnection, count: usize) -> Vec<Uuid> {
+        let workflow = DEFAULT_WORKFLOW.parse().unwrap();
+        let mut ids = Vec::new();
+        for index in 0..count {
+            let new_todo = new_todo(&format!("todo {index}"));
+            ids.push(create_todo(conn, &new_todo, &workflow).await.unwrap());
+        }
+        ids
+    }
+
+    async fn set_parent(
+        conn: &mut SqliteConnection,
+        todo_id: &Uuid,
+        parent_id: &Uuid,
+    ) -> Result<Option<Uuid>> {
+        let changes = UpdateTodoRequest {
+            parent_id: Some(Some(*parent_id)),
+            ..Default::default()
+        };
+        update_todo(conn, todo_id, &changes, &DEFAULT_WORKFLOW.parse().unwrap()).await
+    }
+
+    #[tokio::test]
+    async fn rejects_a_todo_parenting_itself() {
+        let pool = create_test_db_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        let ids = create_todos(&mut conn, 1).await;
+
+        let result = set_parent(&mut conn, &ids[0], &ids[0]).await;
+        assert!(matches!(result, Err(Error::Conflict(_))), "{result:?}");
+    }
+
+    #[tokio::test]
+    async fn rejects_the_parents_closing_a_cycle() {
+        let pool = create_test_db_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        let ids = create_todos(&mut conn, 3).await;
+        set_parent(&mut conn, &ids[1], &ids[0]).await.unwrap();
+        set_parent(&mut conn, &ids[2], &ids[1]).await.unwrap();
+
+        let result = set_parent(&mut conn, &ids[0], &ids[2]).await;
+        assert!(matches!(result, Err(Error::Conflict(_))), "{result:?}");
+        // Moving a descendant up is no cycle
+        set_parent(&mut conn, &ids[2], &ids[0]).await.unwrap();
+        assert_eq!(
+            select_descendant_ids(&mut conn, &ids[0])
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn completes_a_blocked_todo_only_when_forced() {
+        let pool = create_test_db_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        let workflow = DEFAULT_WORKFLOW.parse().unwrap();
+        let ids = create_todos(&mut conn, 2).await;
+        dependencies::add_dependency(&mut conn, &ids[0], &ids[1])
+            .await
+            .unwrap();
+
+        let result = set_todo_completion(&mut conn, &ids[0], true, false, &workflow).await;
+        assert!(matches!(result, Err(Error::Conflict(_))), "{result:?}");
+        let record = select_todo_record(&mut conn, &ids[0])
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!record.todo.completed);
+
+        set_todo_completion(&mut conn, &ids[0], true, true, &workflow)
+            .await
+            .unwrap();
+        let record = select_todo_record(&mut conn, &ids[0])
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(record.todo.completed);
+        assert_eq!(record.todo.state, workflow.terminal);
+    }
+
+    #[tokio::test]
+    async fn completing_a_recurring_todo_creates_the_next_occurrence() {
+        let pool = create_test_db_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        let workflow = DEFAULT_WORKFLOW.parse().unwrap();
+        let due_at = DateTime::parse_from_rfc3339("2026-10-19T09:00:00Z")
+            .unwrap()
+            .to_utc();
+        let new_todo = NewTodoRequest {
+            due_at: Some(due_at),
+            recurrence: Some("FREQ=DAILY".parse().unwrap()),
+            ..new_todo("water the plants")
+        };
+        let todo_id = create_todo(&mut conn, &new_todo, &workflow).await.unwrap();
+
+        let next_id = set_todo_completion(&mut conn, &todo_id, true, false, &workflow)
+            .await
+            .unwrap()
+            .unwrap();
+        let next = select_todo_record(&mut conn, &next_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .todo;
+        assert_eq!(next.content, "water the plants");
+        assert_eq!(next.due_at, Some(due_at + chrono::Duration::days(1)));
+        assert!(!next.completed);
+        assert_eq!(next.recurrence, new_todo.recurrence);
+        // The recurrence was handed over, completing the todo again creates no other occurrence
+        let todo = select_todo_record(&mut conn, &todo_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .todo;
+        assert_eq!(todo.recurrence, None);
+        set_todo_completion(&mut conn, &todo_id, false, false, &workflow)
+            .await
+            .unwrap();
+        let next_id = set_todo_completion(&mut conn, &todo_id, true, false, &workflow)
+            .await
+            .unwrap();
+        assert_eq!(next_id, None);
+    }
+
+    #[tokio::test]
+    async fn moves_a_todo_between_adjacent_todos_only() {
+        let pool = create_test_db_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        let ids = create_todos(&mut conn, 4).await;
+        let between = |after: usize, before: usize| MoveTodoRequest {
+            after: Some(ids[after]),
+            before: Some(ids[before]),
+        };
+
+        let result = move_todo(&mut conn, &ids[3], &between(0, 2)).await;
+        assert!(matches!(result, Err(Error::Invalid(_))), "{result:?}");
+        move_todo(&mut conn, &ids[3], &between(0, 1)).await.unwrap();
+
+        let order: Vec<Uuid> = sqlx::query_scalar("SELECT id FROM todos ORDER BY position")
+            .fetch_all(&mut *conn)
+            .await
+            .unwrap();
+        assert_eq!(order, [ids[0], ids[3], ids[1], ids[2]]);
+    }
+}
@@ -0,0 +1,86 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Overridable with the `workflow` setting: comma separated chains of states, each `>` being an allowed
+/// transition. The first state is the initial one, and the first chain ends with the terminal one.
+pub static DEFAULT_WORKFLOW: &str = "todo>doing>review>done,review>doing,done>todo";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transition {
+    pub from: String,
+    pub to: String,
+}
+
+/// The states a todo goes through, a todo is completed when it reaches the terminal state
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Workflow {
+    /// The first state is the initial one
+    pub states: Vec<String>,
+    pub terminal: String,
+    pub transitions: Vec<Transition>,
+}
+
+impl Workflow {
+    pub fn initial(&self) -> &str {
+        &self.states[0]
+    }
+
+    pub fn contains(&self, state: &str) -> bool {
+        self.states.iter().any(|candidate| candidate == state)
+    }
+
+    pub fn is_terminal(&self, state: &str) -> bool {
+        self.terminal == state
+    }
+
+    pub fn allows(&self, from: &str, to: &str) -> bool {
+        self.transitions
+            .iter()
+            .any(|transition| transition.from == from && transition.to == to)
+    }
+}
+
+impl FromStr for Workflow {
+    type Err = String;
+
+    fn from_str(workflow: &str) -> Result<Self, Self::Err> {
+        let mut states = Vec::<String>::new();
+        let mut terminal = None;
+        let mut transitions = Vec::<Transition>::new();
+
+        for chain in workflow.split(',') {
+            let chain = chain.split('>').map(str::trim).collect::<Vec<_>>();
+            if chain.len() < 2 || chain.iter().any(|state| state.is_empty()) {
+                return Err(format!("invalid workflow chain {}", chain.join(">")));
+            }
+            for state in &chain {
+                if !states.iter().any(|candidate| candidate == state) {
+                    states.push((*state).to_string());
+                }
+            }
+            for pair in chain.windows(2) {
+                let transition = Transition {
+                    from: pair[0].to_string(),
+                    to: pair[1].to_string(),
+                };
+                if !transitions.contains(&transition) {
+                    transitions.push(transition);
+                }
+            }
+            terminal.get_or_insert_with(|| chain[chain.len() - 1].to_string());
+        }
+
+        // There is always at least one chain
+        let terminal = terminal.unwrap_or_default();
+        if terminal == states[0] {
+            return Err("the initial and terminal states must differ".to_string());
+        }
+
+        Ok(Self {
+            states,
+            terminal,
+            transitions,
+        })
+    }
+}